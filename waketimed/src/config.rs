@@ -8,6 +8,7 @@ use std::path::{Path, PathBuf};
 
 const CONFIG_FILE_VAR: &str = "WAKETIMED_CONFIG";
 const DEFAULT_CONFIG_FILE: &str = "/etc/waketimed/config.yaml";
+const ZONEINFO_DIR: &str = "/usr/share/zoneinfo";
 
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Config {
@@ -20,9 +21,19 @@ pub struct Config {
     // directory. It is recommended to specify absolute paths.
     #[serde(default = "default_config_dir")]
     pub config_dir: String,
+    // Directory where waketimed keeps its runtime state across
+    // restarts. Empty string disables state persistence.
+    #[serde(default = "default_state_dir")]
+    pub state_dir: String,
+    // Maximum number of events kept in the suspend/resume history.
+    // Oldest events are dropped first. The history is kept in the
+    // state dir, so it is disabled when state persistence is.
+    #[serde(default = "default_history_max_events")]
+    pub history_max_events: u64,
     // Time between re-checking poll-based variables, in milliseconds.
-    // Larger values mean less exact times of updating variables (less
-    // exact times of falling asleep), but consume less CPU.
+    // Larger values mean variable changes get noticed later, but
+    // consume less CPU. Rules are re-evaluated as soon as a polled
+    // variable changes, and suspend itself is timed precisely.
     #[serde(default = "default_poll_variable_interval")]
     pub poll_variable_interval: u64,
     // Chassis types where waketimed should normally operate. If
@@ -32,6 +43,28 @@ pub struct Config {
     // all chassis types are allowed.
     #[serde(default = "default_allowed_chassis_types")]
     pub allowed_chassis_types: Vec<String>,
+    // Limits for evaluating a rule script, to keep a faulty rule from
+    // stalling waketimed. A rule which exceeds them gets disabled.
+    // Zero means unlimited.
+    #[serde(default = "default_rule_max_operations")]
+    pub rule_max_operations: u64,
+    #[serde(default = "default_rule_max_string_size")]
+    pub rule_max_string_size: usize,
+    #[serde(default = "default_rule_max_array_size")]
+    pub rule_max_array_size: usize,
+    // Checked when compiling rules, scripts can't define functions, so
+    // this also bounds the depth of nested calls.
+    #[serde(default = "default_rule_max_expr_depth")]
+    pub rule_max_expr_depth: usize,
+    // What to do when loading a rule which refers to undefined vars
+    // or uses vars of wrong data type.
+    #[serde(default = "default_rule_check_strictness")]
+    pub rule_check_strictness: RuleCheckStrictness,
+    // Time zone for the local time variables, as an IANA name like
+    // "Europe/Prague" or a POSIX TZ string. Empty string means the
+    // system time zone.
+    #[serde(default = "default_time_zone")]
+    pub time_zone: String,
 
     // Time to stay up (prevent sleep) after waketimed starts, in
     // seconds. Results in automatic creation of a "stay up until"
@@ -49,6 +82,15 @@ pub struct Config {
     // without sending out any "sleep approaching" signals.
     #[serde(default = "default_stayup_cleared_awake_time")]
     pub stayup_cleared_awake_time: u64,
+    // How long before the computed suspend time the "sleep
+    // approaching" signal should be sent out, in milliseconds. Zero
+    // disables the signal.
+    #[serde(default = "default_sleep_approaching_time")]
+    pub sleep_approaching_time: u64,
+    // Whether "sleep approaching" should also be shown as a desktop
+    // notification to users with an active session.
+    #[serde(default = "default_sleep_approaching_notify_desktop")]
+    pub sleep_approaching_notify_desktop: bool,
 
     // Test mode prevents waketimed from actually suspending the
     // system.
@@ -60,6 +102,17 @@ pub struct Config {
     pub test_skip_embedded_defs: bool,
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RuleCheckStrictness {
+    #[serde(rename = "ignore")]
+    Ignore,
+    #[default]
+    #[serde(rename = "warn")]
+    Warn,
+    #[serde(rename = "fail")]
+    Fail,
+}
+
 impl Config {
     pub fn config_dir(&self) -> Option<PathBuf> {
         if self.config_dir.is_empty() {
@@ -69,6 +122,22 @@ impl Config {
         }
     }
 
+    pub fn state_dir(&self) -> Option<PathBuf> {
+        if self.state_dir.is_empty() {
+            None
+        } else {
+            Some(PathBuf::from(&self.state_dir))
+        }
+    }
+
+    pub fn state_file(&self) -> Option<PathBuf> {
+        self.state_dir().map(|dir| dir.join("state.yaml"))
+    }
+
+    pub fn history_file(&self) -> Option<PathBuf> {
+        self.state_dir().map(|dir| dir.join("history.yaml"))
+    }
+
     pub fn config_rule_def_dir(&self) -> Option<PathBuf> {
         self.config_dir().map(|dir| dir.join("rule_def"))
     }
@@ -141,6 +210,12 @@ fn populate_config_from_env(cfg: &mut Config) -> Result<(), AnyError> {
     if let Ok(value) = env::var("WAKETIMED_CONFIG_DIR") {
         cfg.config_dir = value;
     }
+    if let Ok(value) = env::var("WAKETIMED_STATE_DIR") {
+        cfg.state_dir = value;
+    }
+    if let Ok(value) = env::var("WAKETIMED_HISTORY_MAX_EVENTS") {
+        cfg.history_max_events = value.parse::<u64>()?;
+    }
     if let Ok(value) = env::var("WAKETIMED_STARTUP_AWAKE_TIME") {
         cfg.startup_awake_time = value.parse::<u64>()?;
     }
@@ -150,12 +225,36 @@ fn populate_config_from_env(cfg: &mut Config) -> Result<(), AnyError> {
     if let Ok(value) = env::var("WAKETIMED_STAYUP_CLEARED_AWAKE_TIME") {
         cfg.stayup_cleared_awake_time = value.parse::<u64>()?;
     }
+    if let Ok(value) = env::var("WAKETIMED_SLEEP_APPROACHING_TIME") {
+        cfg.sleep_approaching_time = value.parse::<u64>()?;
+    }
+    if let Ok(value) = env::var("WAKETIMED_SLEEP_APPROACHING_NOTIFY_DESKTOP") {
+        cfg.sleep_approaching_notify_desktop = value.parse::<bool>()?;
+    }
     if let Ok(value) = env::var("WAKETIMED_POLL_VARIABLE_INTERVAL") {
         cfg.poll_variable_interval = value.parse::<u64>()?;
     }
     if let Ok(value) = env::var("WAKETIMED_ALLOWED_CHASSIS_TYPES") {
         cfg.allowed_chassis_types = value.split(',').map(|s| s.to_string()).collect();
     }
+    if let Ok(value) = env::var("WAKETIMED_RULE_MAX_OPERATIONS") {
+        cfg.rule_max_operations = value.parse::<u64>()?;
+    }
+    if let Ok(value) = env::var("WAKETIMED_RULE_MAX_STRING_SIZE") {
+        cfg.rule_max_string_size = value.parse::<usize>()?;
+    }
+    if let Ok(value) = env::var("WAKETIMED_RULE_MAX_ARRAY_SIZE") {
+        cfg.rule_max_array_size = value.parse::<usize>()?;
+    }
+    if let Ok(value) = env::var("WAKETIMED_RULE_MAX_EXPR_DEPTH") {
+        cfg.rule_max_expr_depth = value.parse::<usize>()?;
+    }
+    if let Ok(value) = env::var("WAKETIMED_RULE_CHECK_STRICTNESS") {
+        cfg.rule_check_strictness = serde_yaml::from_str(&value)?;
+    }
+    if let Ok(value) = env::var("WAKETIMED_TIME_ZONE") {
+        cfg.time_zone = value;
+    }
     if let Ok(value) = env::var("WAKETIMED_TEST_MODE") {
         cfg.test_mode = value.parse::<bool>()?;
     }
@@ -167,6 +266,8 @@ fn populate_config_from_env(cfg: &mut Config) -> Result<(), AnyError> {
 
 fn check_and_repair_config(cfg: &mut Config) -> Result<(), AnyError> {
     check_config_dir(cfg)?;
+    check_state_dir(cfg)?;
+    check_time_zone(cfg)?;
     Ok(())
 }
 
@@ -192,6 +293,41 @@ fn check_config_dir(cfg: &Config) -> Result<(), AnyError> {
     Ok(())
 }
 
+fn check_state_dir(cfg: &Config) -> Result<(), AnyError> {
+    // Default state dir gets created when saving state.
+    if cfg.state_dir == default_state_dir() {
+        return Ok(());
+    }
+
+    if let Some(dir) = cfg.state_dir() {
+        if !dir.is_dir() {
+            return Err(anyhow!(
+                "Non-default state dir '{}' specified, but it is not an existing directory.",
+                cfg.state_dir
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn check_time_zone(cfg: &Config) -> Result<(), AnyError> {
+    // POSIX TZ strings always contain an offset, so anything without
+    // digits has to be a name from the time zone database. An invalid
+    // name would otherwise silently fall back to UTC.
+    let tz = cfg.time_zone.trim_start_matches(':');
+    if tz.is_empty() || tz.contains(|c: char| c.is_ascii_digit()) {
+        return Ok(());
+    }
+    if !Path::new(ZONEINFO_DIR).join(tz).is_file() {
+        return Err(anyhow!(
+            "Time zone '{}' not found in '{}'.",
+            cfg.time_zone,
+            ZONEINFO_DIR
+        ));
+    }
+    Ok(())
+}
+
 fn default_log() -> String {
     "info".to_string()
 }
@@ -200,6 +336,14 @@ fn default_config_dir() -> String {
     "/etc/waketimed".to_string()
 }
 
+fn default_state_dir() -> String {
+    "/var/lib/waketimed".to_string()
+}
+
+fn default_history_max_events() -> u64 {
+    1000
+}
+
 fn default_startup_awake_time() -> u64 {
     300_000
 }
@@ -212,6 +356,14 @@ fn default_stayup_cleared_awake_time() -> u64 {
     10_000
 }
 
+fn default_sleep_approaching_time() -> u64 {
+    5_000
+}
+
+fn default_sleep_approaching_notify_desktop() -> bool {
+    false
+}
+
 fn default_poll_variable_interval() -> u64 {
     3_000
 }
@@ -226,6 +378,30 @@ fn default_allowed_chassis_types() -> Vec<String> {
     ]
 }
 
+fn default_rule_max_operations() -> u64 {
+    100_000
+}
+
+fn default_rule_max_string_size() -> usize {
+    10_000
+}
+
+fn default_rule_max_array_size() -> usize {
+    1_000
+}
+
+fn default_rule_max_expr_depth() -> usize {
+    64
+}
+
+fn default_rule_check_strictness() -> RuleCheckStrictness {
+    RuleCheckStrictness::Warn
+}
+
+fn default_time_zone() -> String {
+    "".to_string()
+}
+
 fn default_test_mode() -> bool {
     false
 }
@@ -0,0 +1,42 @@
+use crate::core::rules::RuleName;
+use chrono::{DateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEvent {
+    pub time: DateTime<Utc>,
+    #[serde(with = "serde_yaml::with::singleton_map")]
+    pub kind: HistoryEventKind,
+}
+
+impl HistoryEvent {
+    pub fn new(time: DateTime<Utc>, kind: HistoryEventKind) -> Self {
+        Self { time, kind }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HistoryEventKind {
+    /// Waketimed engine started running.
+    #[serde(rename = "started")]
+    Started,
+    /// Waketimed terminated.
+    #[serde(rename = "stopped")]
+    Stopped,
+    /// Waketimed asked the login manager to suspend the system.
+    #[serde(rename = "suspend_requested")]
+    SuspendRequested,
+    /// The suspend request was refused or failed.
+    #[serde(rename = "suspend_failed")]
+    SuspendFailed { error: String },
+    /// The system is going to sleep (PrepareForSleep signal).
+    #[serde(rename = "suspending")]
+    Suspending,
+    /// The system woke up (PrepareForSleep signal).
+    #[serde(rename = "resumed")]
+    Resumed { wake_reason: String },
+    /// A stay-up rule was active, blocking sleep for `active_ms`
+    /// milliseconds. Recorded when the rule becomes inactive.
+    #[serde(rename = "stayup_rule_active")]
+    StayupRuleActive { rule: RuleName, active_ms: u64 },
+}
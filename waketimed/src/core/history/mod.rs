@@ -0,0 +1,5 @@
+mod event;
+mod stats;
+
+pub use event::{HistoryEvent, HistoryEventKind};
+pub use stats::compute_stats;
@@ -0,0 +1,258 @@
+use super::{HistoryEvent, HistoryEventKind};
+use crate::core::rules::RuleName;
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+const TOP_BLOCKING_RULES: usize = 10;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryStats {
+    pub days: Vec<DayStats>,
+    pub total_suspend_cycles: u64,
+    pub failed_suspends: u64,
+    pub wake_reasons: BTreeMap<String, u64>,
+    pub top_blocking_rules: Vec<RuleStats>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DayStats {
+    pub date: NaiveDate,
+    pub asleep_ms: u64,
+    pub awake_ms: u64,
+    pub suspend_cycles: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuleStats {
+    pub rule: RuleName,
+    pub blocking_ms: u64,
+    pub activations: u64,
+}
+
+#[derive(Clone, Copy)]
+enum SleepState {
+    Unknown,
+    Awake(DateTime<Utc>),
+    Asleep(DateTime<Utc>),
+}
+
+/// Aggregate history events into statistics. Days are computed in
+/// the given time zone. Time of the ongoing awake period is counted
+/// up until `now`.
+pub fn compute_stats<Tz: TimeZone>(
+    events: &[HistoryEvent],
+    now: DateTime<Utc>,
+    tz: &Tz,
+) -> HistoryStats {
+    let mut stats = HistoryStats::default();
+    let mut days: BTreeMap<NaiveDate, DayStats> = BTreeMap::new();
+    let mut rules: HashMap<RuleName, RuleStats> = HashMap::new();
+    let mut state = SleepState::Unknown;
+
+    for event in events.iter() {
+        use HistoryEventKind::*;
+        match &event.kind {
+            Started => {
+                state = SleepState::Awake(event.time);
+            }
+            Stopped => {
+                if let SleepState::Awake(since) = state {
+                    add_interval(&mut days, tz, since, event.time, false);
+                }
+                state = SleepState::Unknown;
+            }
+            SuspendRequested => {}
+            SuspendFailed { .. } => {
+                stats.failed_suspends += 1;
+            }
+            Suspending => {
+                if let SleepState::Awake(since) = state {
+                    add_interval(&mut days, tz, since, event.time, false);
+                }
+                stats.total_suspend_cycles += 1;
+                day_stats(&mut days, event.time.with_timezone(tz).date_naive()).suspend_cycles += 1;
+                state = SleepState::Asleep(event.time);
+            }
+            Resumed { wake_reason } => {
+                if let SleepState::Asleep(since) = state {
+                    add_interval(&mut days, tz, since, event.time, true);
+                }
+                *stats.wake_reasons.entry(wake_reason.clone()).or_insert(0) += 1;
+                state = SleepState::Awake(event.time);
+            }
+            StayupRuleActive { rule, active_ms } => {
+                let rule_stats = rules.entry(rule.clone()).or_insert_with(|| RuleStats {
+                    rule: rule.clone(),
+                    blocking_ms: 0,
+                    activations: 0,
+                });
+                rule_stats.blocking_ms += active_ms;
+                rule_stats.activations += 1;
+            }
+        }
+    }
+    if let SleepState::Awake(since) = state {
+        add_interval(&mut days, tz, since, now, false);
+    }
+
+    stats.days = days.into_values().collect();
+    let mut top_rules: Vec<RuleStats> = rules.into_values().collect();
+    top_rules.sort_by(|a, b| {
+        b.blocking_ms
+            .cmp(&a.blocking_ms)
+            .then_with(|| a.rule.as_ref().cmp(b.rule.as_ref()))
+    });
+    top_rules.truncate(TOP_BLOCKING_RULES);
+    stats.top_blocking_rules = top_rules;
+    stats
+}
+
+fn day_stats(days: &mut BTreeMap<NaiveDate, DayStats>, date: NaiveDate) -> &mut DayStats {
+    days.entry(date).or_insert_with(|| DayStats {
+        date,
+        asleep_ms: 0,
+        awake_ms: 0,
+        suspend_cycles: 0,
+    })
+}
+
+// Add the interval to per-day stats, splitting it at midnights.
+fn add_interval<Tz: TimeZone>(
+    days: &mut BTreeMap<NaiveDate, DayStats>,
+    tz: &Tz,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    asleep: bool,
+) {
+    let mut from = from;
+    while from < to {
+        let date = from.with_timezone(tz).date_naive();
+        let next_midnight = date
+            .succ_opt()
+            .and_then(|next_date| next_date.and_hms_opt(0, 0, 0))
+            .and_then(|naive| tz.from_local_datetime(&naive).earliest())
+            .map(|midnight| midnight.with_timezone(&Utc))
+            .unwrap_or(to);
+        let until = next_midnight.min(to).max(from);
+        let millis = (until - from).num_milliseconds().max(0) as u64;
+        let day = day_stats(days, date);
+        if asleep {
+            day.asleep_ms += millis;
+        } else {
+            day.awake_ms += millis;
+        }
+        if until == from {
+            break;
+        }
+        from = until;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::rule_name;
+
+    fn at(day: u32, hour: u32, min: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2023, 3, day, hour, min, 0).unwrap()
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2023, 3, day).unwrap()
+    }
+
+    #[test]
+    fn test_compute_stats() {
+        use HistoryEventKind::*;
+        let events = vec![
+            HistoryEvent::new(at(1, 22, 0), Started),
+            HistoryEvent::new(
+                at(1, 22, 30),
+                StayupRuleActive {
+                    rule: rule_name("wtd_user_busy"),
+                    active_ms: 1_200_000,
+                },
+            ),
+            HistoryEvent::new(at(1, 23, 0), SuspendRequested),
+            HistoryEvent::new(
+                at(1, 23, 0),
+                SuspendFailed {
+                    error: "denied".to_string(),
+                },
+            ),
+            HistoryEvent::new(at(1, 23, 30), SuspendRequested),
+            HistoryEvent::new(at(1, 23, 30), Suspending),
+            HistoryEvent::new(
+                at(2, 1, 0),
+                Resumed {
+                    wake_reason: "modem".to_string(),
+                },
+            ),
+            HistoryEvent::new(
+                at(2, 1, 10),
+                StayupRuleActive {
+                    rule: rule_name("wtd_call_present"),
+                    active_ms: 600_000,
+                },
+            ),
+            HistoryEvent::new(
+                at(2, 1, 20),
+                StayupRuleActive {
+                    rule: rule_name("wtd_user_busy"),
+                    active_ms: 60_000,
+                },
+            ),
+            HistoryEvent::new(at(2, 1, 30), Stopped),
+        ];
+        let stats = compute_stats(&events, at(2, 12, 0), &Utc);
+
+        assert_eq!(stats.total_suspend_cycles, 1);
+        assert_eq!(stats.failed_suspends, 1);
+        assert_eq!(
+            stats.wake_reasons,
+            BTreeMap::from([("modem".to_string(), 1)])
+        );
+        assert_eq!(
+            stats.days,
+            vec![
+                DayStats {
+                    date: date(1),
+                    asleep_ms: 30 * 60_000,
+                    awake_ms: 90 * 60_000,
+                    suspend_cycles: 1,
+                },
+                DayStats {
+                    date: date(2),
+                    asleep_ms: 60 * 60_000,
+                    awake_ms: 30 * 60_000,
+                    suspend_cycles: 0,
+                },
+            ]
+        );
+        assert_eq!(
+            stats.top_blocking_rules,
+            vec![
+                RuleStats {
+                    rule: rule_name("wtd_user_busy"),
+                    blocking_ms: 1_260_000,
+                    activations: 2,
+                },
+                RuleStats {
+                    rule: rule_name("wtd_call_present"),
+                    blocking_ms: 600_000,
+                    activations: 1,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compute_stats_ongoing_awake() {
+        let events = vec![HistoryEvent::new(at(3, 10, 0), HistoryEventKind::Started)];
+        let stats = compute_stats(&events, at(3, 10, 15), &Utc);
+        assert_eq!(stats.days.len(), 1);
+        assert_eq!(stats.days[0].awake_ms, 15 * 60_000);
+        assert_eq!(stats.days[0].asleep_ms, 0);
+    }
+}
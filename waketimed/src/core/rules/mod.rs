@@ -6,4 +6,3 @@ mod params;
 pub use def::{RuleDef, RuleKind};
 pub use error::{RuleError, RuleNameError};
 pub use name::RuleName;
-pub use params::param_required;
@@ -12,6 +12,7 @@ where
     from_value(value).map_err(|e| RuleError::IncorrectParamType(key.to_string(), e))
 }
 
+#[allow(clippy::map_clone)]
 pub fn param_required_value(
     params: &HashMap<String, Value>,
     key: &str,
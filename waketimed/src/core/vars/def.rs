@@ -27,6 +27,12 @@ impl VarDef {
 pub enum VarDataType {
     #[serde(rename = "bool")]
     Bool,
+    #[serde(rename = "int")]
+    Int,
+    #[serde(rename = "float")]
+    Float,
+    #[serde(rename = "string")]
+    String,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -35,6 +41,10 @@ pub enum VarKind {
     /// built into waketimed.
     #[serde(rename = "builtin_poll")]
     BuiltinPoll(BuiltinPollDef),
+    /// Variable set by the waketimed engine itself, based on events
+    /// the engine observes (e.g. the system resuming from sleep).
+    #[serde(rename = "builtin_engine")]
+    BuiltinEngine(BuiltinEngineDef),
     /// Boolean variable which is true if any variables in the
     /// specified category_name are true. If all such variables are
     /// false or if there are no such variables defined/active, the
@@ -50,6 +60,11 @@ pub struct BuiltinPollDef {
     pub params: HashMap<String, Value>,
 }
 
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuiltinEngineDef {
+    pub builtin_name: String,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct CategoryAnyDef {
     pub category_name: VarName,
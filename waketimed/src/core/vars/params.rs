@@ -11,6 +11,19 @@ where
     from_value(value).map_err(|e| VarError::IncorrectParamType(key.to_string(), e))
 }
 
+pub fn param_optional<T>(params: &HashMap<String, Value>, key: &str) -> Result<Option<T>, VarError>
+where
+    T: DeserializeOwned,
+{
+    params
+        .get(key)
+        .map(|value| {
+            from_value(value.clone()).map_err(|e| VarError::IncorrectParamType(key.to_string(), e))
+        })
+        .transpose()
+}
+
+#[allow(clippy::map_clone)]
 pub fn param_required_value(params: &HashMap<String, Value>, key: &str) -> Result<Value, VarError> {
     params
         .get(key)
@@ -43,4 +56,16 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_param_optional() -> Result<(), VarError> {
+        let params = create_params();
+        assert_eq!(
+            Some("a val".to_string()),
+            param_optional::<String>(&params, "a key")?
+        );
+        assert_eq!(None, param_optional::<String>(&params, "c key")?);
+        assert!(param_optional::<u64>(&params, "a key").is_err());
+        Ok(())
+    }
 }
@@ -1,16 +1,34 @@
+use super::VarDataType;
 use serde_derive::{Deserialize, Serialize};
 use std::fmt;
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum VarValue {
     Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+}
+
+impl VarValue {
+    pub fn data_type(&self) -> VarDataType {
+        match self {
+            VarValue::Bool(_) => VarDataType::Bool,
+            VarValue::Int(_) => VarDataType::Int,
+            VarValue::Float(_) => VarDataType::Float,
+            VarValue::String(_) => VarDataType::String,
+        }
+    }
 }
 
 impl fmt::Display for VarValue {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
         match self {
             VarValue::Bool(v) => write!(f, "{v}"),
+            VarValue::Int(v) => write!(f, "{v}"),
+            VarValue::Float(v) => write!(f, "{v:?}"),
+            VarValue::String(v) => write!(f, "{v:?}"),
         }
     }
 }
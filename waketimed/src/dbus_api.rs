@@ -0,0 +1,8 @@
+//! Names under which waketimed presents itself on D-Bus.
+
+pub const BUS_NAME: &str = "io.github.jistr.Waketimed";
+pub const OBJECT_PATH: &str = "/io/github/jistr/Waketimed";
+pub const INTERFACE: &str = "io.github.jistr.Waketimed";
+
+pub const SIGNAL_SLEEP_APPROACHING: &str = "SleepApproaching";
+pub const SIGNAL_SLEEP_APPROACHING_CANCELLED: &str = "SleepApproachingCancelled";
@@ -68,9 +68,14 @@ mod tests {
         assert_eq!(
             &ref_entries,
             &[
+                "/__WAKETIMED_EMBEDDED__/rule_def/wtd_audio_active.yaml",
                 "/__WAKETIMED_EMBEDDED__/rule_def/wtd_call_present.yaml",
+                "/__WAKETIMED_EMBEDDED__/rule_def/wtd_media_playing.yaml",
+                "/__WAKETIMED_EMBEDDED__/rule_def/wtd_modem_sms_received.yaml",
+                "/__WAKETIMED_EMBEDDED__/rule_def/wtd_remote_session.yaml",
                 "/__WAKETIMED_EMBEDDED__/rule_def/wtd_sleep_block_inhibited.yaml",
-                "/__WAKETIMED_EMBEDDED__/rule_def/wtd_user_busy.yaml"
+                "/__WAKETIMED_EMBEDDED__/rule_def/wtd_user_busy.yaml",
+                "/__WAKETIMED_EMBEDDED__/rule_def/wtd_wake_lock_held.yaml"
             ],
         );
         Ok(())
@@ -87,11 +92,42 @@ mod tests {
         assert_eq!(
             &ref_entries,
             &[
+                "/__WAKETIMED_EMBEDDED__/var_def/wtd_alsa_playback_active.yaml",
+                "/__WAKETIMED_EMBEDDED__/var_def/wtd_audio_active.yaml",
+                "/__WAKETIMED_EMBEDDED__/var_def/wtd_backlight_on.yaml",
+                "/__WAKETIMED_EMBEDDED__/var_def/wtd_battery_percent.yaml",
+                "/__WAKETIMED_EMBEDDED__/var_def/wtd_bluetooth_connected.yaml",
+                "/__WAKETIMED_EMBEDDED__/var_def/wtd_bluez_audio_active.yaml",
                 "/__WAKETIMED_EMBEDDED__/var_def/wtd_call_present.yaml",
+                "/__WAKETIMED_EMBEDDED__/var_def/wtd_charging.yaml",
+                "/__WAKETIMED_EMBEDDED__/var_def/wtd_cpu_utilization.yaml",
+                "/__WAKETIMED_EMBEDDED__/var_def/wtd_display_on.yaml",
+                "/__WAKETIMED_EMBEDDED__/var_def/wtd_lid_closed.yaml",
+                "/__WAKETIMED_EMBEDDED__/var_def/wtd_load_average.yaml",
+                "/__WAKETIMED_EMBEDDED__/var_def/wtd_local_date.yaml",
+                "/__WAKETIMED_EMBEDDED__/var_def/wtd_local_hour.yaml",
+                "/__WAKETIMED_EMBEDDED__/var_def/wtd_local_minute.yaml",
+                "/__WAKETIMED_EMBEDDED__/var_def/wtd_local_weekday.yaml",
                 "/__WAKETIMED_EMBEDDED__/var_def/wtd_login_seat_busy.yaml",
+                "/__WAKETIMED_EMBEDDED__/var_def/wtd_login_seat_idle_ms.yaml",
+                "/__WAKETIMED_EMBEDDED__/var_def/wtd_media_playing.yaml",
+                "/__WAKETIMED_EMBEDDED__/var_def/wtd_modem_sms_received.yaml",
                 "/__WAKETIMED_EMBEDDED__/var_def/wtd_modem_voice_call_present.yaml",
+                "/__WAKETIMED_EMBEDDED__/var_def/wtd_mpris_media_playing.yaml",
+                "/__WAKETIMED_EMBEDDED__/var_def/wtd_network_busy.yaml",
+                "/__WAKETIMED_EMBEDDED__/var_def/wtd_network_connection_type.yaml",
+                "/__WAKETIMED_EMBEDDED__/var_def/wtd_network_connectivity.yaml",
+                "/__WAKETIMED_EMBEDDED__/var_def/wtd_network_metered.yaml",
+                "/__WAKETIMED_EMBEDDED__/var_def/wtd_on_ac_power.yaml",
+                "/__WAKETIMED_EMBEDDED__/var_def/wtd_power_supply_charging.yaml",
+                "/__WAKETIMED_EMBEDDED__/var_def/wtd_power_supply_on_ac_power.yaml",
+                "/__WAKETIMED_EMBEDDED__/var_def/wtd_remote_session.yaml",
                 "/__WAKETIMED_EMBEDDED__/var_def/wtd_sleep_block_inhibited.yaml",
+                "/__WAKETIMED_EMBEDDED__/var_def/wtd_thermal_max_celsius.yaml",
                 "/__WAKETIMED_EMBEDDED__/var_def/wtd_user_busy.yaml",
+                "/__WAKETIMED_EMBEDDED__/var_def/wtd_wake_lock_held.yaml",
+                "/__WAKETIMED_EMBEDDED__/var_def/wtd_wake_reason.yaml",
+                "/__WAKETIMED_EMBEDDED__/var_def/wtd_wakeup_source_active.yaml",
             ],
         );
         Ok(())
@@ -1,11 +1,17 @@
 use crate::chassis_check;
 use crate::config::Config;
+use crate::core::history::HistoryEventKind;
 use crate::core::vars::{VarName, VarValue};
+use crate::history_manager::HistoryManager;
 use crate::messages::{EngineMsg, WorkerMsg};
+use crate::persistent_state::{self, PersistentState};
+use crate::rule_fns::EvalTime;
 use crate::rule_manager::RuleManager;
 use crate::sleep_manager::SleepManager;
+use crate::time;
 use crate::var_manager::VarManager;
 use anyhow::{Context, Error as AnyError};
+use chrono::Utc;
 use log::{debug, error, info, trace, warn};
 use std::rc::Rc;
 use tokio::sync::mpsc::UnboundedSender;
@@ -15,6 +21,7 @@ pub struct Engine {
     worker_send: UnboundedSender<WorkerMsg>,
 
     cfg: Rc<Config>,
+    history_manager: HistoryManager,
     rule_manager: RuleManager,
     sleep_manager: SleepManager,
     state: EngineState,
@@ -27,6 +34,7 @@ impl Engine {
         engine_send: UnboundedSender<EngineMsg>,
         worker_send: UnboundedSender<WorkerMsg>,
     ) -> Result<Self, AnyError> {
+        let history_manager = HistoryManager::new(cfg.clone());
         let rule_manager = RuleManager::new(cfg.clone());
         let sleep_manager = SleepManager::new(cfg.clone(), worker_send.clone());
         let var_manager = VarManager::new(cfg.clone(), worker_send.clone())?;
@@ -34,6 +42,7 @@ impl Engine {
             engine_send,
             worker_send,
             cfg,
+            history_manager,
             rule_manager,
             sleep_manager,
             state: EngineState::Initializing,
@@ -55,12 +64,22 @@ impl Engine {
             return Ok(());
         }
 
-        self.rule_manager.init()?;
-        self.sleep_manager.init()?;
+        self.history_manager.init()?;
         self.var_manager.init()?;
+        self.rule_manager.init(
+            self.var_manager.var_defs(),
+            self.var_manager.category_vars().clone(),
+            time::now()?,
+        )?;
+        self.sleep_manager.init()?;
+        self.restore_state();
+        self.publish_wake_reason();
         self.worker_send
             .send(WorkerMsg::WatchPrepareForSleep)
             .expect("Failed to send WorkerMsg::WatchPrepareForSleep");
+        self.worker_send
+            .send(WorkerMsg::ServeControlInterface(self.cfg.history_file()))
+            .expect("Failed to send WorkerMsg::ServeControlInterface");
         self.set_state(EngineState::Running);
         Ok(())
     }
@@ -89,9 +108,16 @@ impl Engine {
                 EngineMsg::ReturnVarPoll(var_name, opt_value) => {
                     self.handle_return_var_poll(var_name, opt_value)
                 }
-                EngineMsg::SystemIsResuming => self.sleep_manager.handle_system_is_resuming(),
-                EngineMsg::SystemIsSuspending => self.sleep_manager.handle_system_is_suspending(),
+                EngineMsg::SuspendFailed(error) => self.handle_suspend_failed(error),
+                EngineMsg::SuspendTick => self.handle_suspend_tick(),
+                EngineMsg::SystemIsResuming => self.handle_system_is_resuming(),
+                EngineMsg::SystemIsSuspending => self.handle_system_is_suspending(),
                 EngineMsg::Terminate => {
+                    self.save_state();
+                    let now = Utc::now();
+                    self.history_manager.finish_active_stayup_rules(now);
+                    self.history_manager.record(now, HistoryEventKind::Stopped);
+                    self.history_manager.save_if_changed();
                     self.handle_terminate();
                 }
                 #[allow(unreachable_patterns)]
@@ -139,25 +165,120 @@ impl Engine {
     }
 
     fn handle_return_var_poll(&mut self, var_name: VarName, opt_value: Option<VarValue>) {
-        self.var_manager.handle_return_var_poll(var_name, opt_value);
-        if self.var_manager.waitlist_poll_is_empty() {
+        let changed = self.var_manager.handle_return_var_poll(var_name, opt_value);
+        // Re-evaluate right away on change, don't wait for the
+        // remaining polls to return.
+        if changed || self.var_manager.waitlist_poll_is_empty() {
             self.engine_tick();
         }
     }
 
+    fn handle_suspend_failed(&mut self, error: String) {
+        self.history_manager
+            .record_suspend_failed(Utc::now(), error);
+    }
+
+    fn handle_suspend_tick(&mut self) {
+        self.sleep_manager.handle_suspend_tick();
+        self.engine_tick();
+    }
+
+    fn handle_system_is_suspending(&mut self) {
+        self.sleep_manager.handle_system_is_suspending();
+        self.history_manager
+            .record(Utc::now(), HistoryEventKind::Suspending);
+        self.history_manager.save_if_changed();
+        self.save_state();
+    }
+
+    fn handle_system_is_resuming(&mut self) {
+        self.sleep_manager.handle_system_is_resuming();
+        self.history_manager.record(
+            Utc::now(),
+            HistoryEventKind::Resumed {
+                wake_reason: self.sleep_manager.wake_reason().to_string(),
+            },
+        );
+        self.publish_wake_reason();
+        self.engine_tick();
+    }
+
+    fn restore_state(&mut self) {
+        let state_file = match self.cfg.state_file() {
+            Some(path) => path,
+            None => return,
+        };
+        let state = match persistent_state::load(&state_file) {
+            Some(state) => state,
+            None => return,
+        };
+        if !state.is_from_current_boot() {
+            debug!("Saved state is from a previous boot, not restoring it.");
+            return;
+        }
+        info!("Restoring state saved at {}.", state.saved_at);
+        self.var_manager.restore_vars(&state.vars);
+        let result = self
+            .sleep_manager
+            .restore(&state.sleep)
+            .context("Failed to restore SleepManager state.");
+        self.term_on_err(result);
+    }
+
+    fn save_state(&mut self) {
+        let state_file = match self.cfg.state_file() {
+            Some(path) => path,
+            None => return,
+        };
+        let result = PersistentState::new(
+            self.sleep_manager.persistent_state(),
+            self.var_manager.vars().clone(),
+        )
+        .and_then(|state| persistent_state::save(&state_file, &state));
+        if let Err(e) = result {
+            // Failing to save state shouldn't bring the daemon down.
+            warn!("Failed to save state: {:#}", e);
+        }
+    }
+
+    fn publish_wake_reason(&mut self) {
+        let wake_reason = self.sleep_manager.wake_reason();
+        self.var_manager
+            .set_builtin_engine_var("wake_reason", VarValue::String(wake_reason.to_string()));
+    }
+
     fn engine_tick(&mut self) {
         let result = self.update_everything();
         self.term_on_err(result);
+        let result = self.sleep_manager.signal_sleep_approaching_if_due();
+        self.term_on_err(result);
         let result = self.sleep_manager.suspend_if_allowed();
+        if let Some(true) = self.term_on_err(result) {
+            self.history_manager.record_suspend_requested(Utc::now());
+        }
+        let result = self.sleep_manager.schedule_suspend_tick();
         self.term_on_err(result);
+        self.history_manager.save_if_changed();
     }
 
     fn update_everything(&mut self) -> Result<(), AnyError> {
         trace!("Executing Engine logic update routine.");
+        let local_now = time::local_now();
+        let eval_time = EvalTime {
+            now: time::now()?,
+            unix_time_ms: local_now.timestamp_millis(),
+            local_time: local_now.time(),
+        };
+        self.var_manager.update_local_time_vars(&local_now);
         self.var_manager.update_category_vars();
+        let changed_vars = self.var_manager.take_changed_vars();
         self.rule_manager
-            .reset_script_scope(self.var_manager.vars());
-        self.rule_manager.compute_stayup_values();
+            .update_script_scope(self.var_manager.vars(), &changed_vars, &eval_time);
+        self.rule_manager.compute_stayup_values(&changed_vars);
+        self.history_manager.update_active_stayup_rules(
+            local_now.with_timezone(&Utc),
+            &self.rule_manager.active_stayup_rules(),
+        );
         self.sleep_manager
             .update(self.rule_manager.is_stayup_active())
             .context("Failed to update SleepManager")?;
@@ -173,6 +294,8 @@ impl Engine {
                     .spawn_poll_var_interval()
                     .context("Fatal: Failed to set up variable poll interval.");
                 self.term_on_err(res);
+                self.history_manager
+                    .record(Utc::now(), HistoryEventKind::Started);
                 self.sleep_manager.log_info_nearest_possible_suspend();
             }
             _ => {}
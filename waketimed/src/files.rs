@@ -7,8 +7,8 @@ use log::debug;
 use serde::de::DeserializeOwned;
 use std::collections::{HashMap, HashSet};
 use std::ffi::OsString;
-use std::fs::{File, ReadDir};
-use std::io::{BufRead, BufReader};
+use std::fs::{self, File, ReadDir};
+use std::io::{BufRead, BufReader, Write};
 use std::path::{Path, PathBuf};
 
 pub fn load_rule_defs(cfg: &Config) -> Result<HashMap<RuleName, RuleDef>, AnyError> {
@@ -61,6 +61,27 @@ pub fn load_var_defs(cfg: &Config) -> Result<HashMap<VarName, VarDef>, AnyError>
     Ok(var_defs)
 }
 
+/// Write `content` to `path` atomically, so that a crash or power
+/// loss mid-write can't leave a truncated file behind. Parent
+/// directories are created if needed.
+pub fn write_atomically<P: AsRef<Path>>(path: P, content: &[u8]) -> Result<(), AnyError> {
+    let path = path.as_ref();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create directory '{}'", dir.display()))?;
+    }
+    let mut tmp_path_string = path.as_os_str().to_os_string();
+    tmp_path_string.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path_string);
+    let mut tmp_file = File::create(&tmp_path)
+        .with_context(|| format!("Failed to create file '{}'", tmp_path.display()))?;
+    tmp_file.write_all(content)?;
+    tmp_file.sync_all()?;
+    fs::rename(&tmp_path, path)
+        .with_context(|| format!("Failed to replace file '{}'", path.display()))?;
+    Ok(())
+}
+
 fn parse_rule_def<P: AsRef<Path>>(def_path: P) -> Result<Option<RuleDef>, AnyError> {
     let raw_name = def_path
         .as_ref()
@@ -115,7 +136,7 @@ fn parse_embedded_yaml_file_unless_empty<T: DeserializeOwned, P: AsRef<Path>>(
     file_path: P,
 ) -> Result<Option<T>, AnyError> {
     let data = embedded_files::embedded_file_data(file_path.as_ref())?;
-    if data.len() == 0 {
+    if data.is_empty() {
         return Ok(None);
     }
 
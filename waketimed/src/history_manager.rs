@@ -0,0 +1,292 @@
+use crate::config::Config;
+use crate::core::history::{self, HistoryEvent, HistoryEventKind};
+use crate::core::rules::RuleName;
+use crate::files;
+use anyhow::{Context, Error as AnyError};
+use chrono::{DateTime, Local, Utc};
+use log::{debug, warn};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+/// Keeps a bounded journal of suspend/resume related events, which
+/// can be aggregated into statistics. Recorded events are written to
+/// the history file in batches, via `save_if_changed`. Times of events
+/// are passed in by the caller.
+pub struct HistoryManager {
+    cfg: Rc<Config>,
+    events: VecDeque<HistoryEvent>,
+    stayup_rules_active_since: HashMap<RuleName, DateTime<Utc>>,
+    unsaved_changes: bool,
+    // State of the current suspend episode, i.e. suspend requests
+    // since the last event of another kind.
+    suspend_requested: bool,
+    suspend_failed: bool,
+}
+
+impl HistoryManager {
+    pub fn new(cfg: Rc<Config>) -> Self {
+        Self {
+            cfg,
+            events: VecDeque::new(),
+            stayup_rules_active_since: HashMap::new(),
+            unsaved_changes: false,
+            suspend_requested: false,
+            suspend_failed: false,
+        }
+    }
+
+    pub fn init(&mut self) -> Result<(), AnyError> {
+        if let Some(history_file) = self.cfg.history_file() {
+            let events = load_events(&history_file).unwrap_or_else(|e| {
+                warn!("Failed to load history, starting a new one: {:#}", e);
+                Vec::new()
+            });
+            self.events = events.into();
+            self.truncate();
+        }
+        Ok(())
+    }
+
+    pub fn record(&mut self, now: DateTime<Utc>, kind: HistoryEventKind) {
+        debug!("Recording history event: {:?}", &kind);
+        match kind {
+            HistoryEventKind::SuspendRequested => self.suspend_requested = true,
+            HistoryEventKind::SuspendFailed { .. } => self.suspend_failed = true,
+            _ => {
+                self.suspend_requested = false;
+                self.suspend_failed = false;
+            }
+        }
+        self.events.push_back(HistoryEvent::new(now, kind));
+        self.truncate();
+        self.unsaved_changes = true;
+    }
+
+    /// Record a suspend request. Suspend gets re-requested on each
+    /// engine tick until the system starts suspending, also after a
+    /// failed request. Only the first request of such an episode is
+    /// recorded.
+    pub fn record_suspend_requested(&mut self, now: DateTime<Utc>) {
+        if self.suspend_requested {
+            return;
+        }
+        self.record(now, HistoryEventKind::SuspendRequested);
+    }
+
+    /// Record a failed suspend request. Only the first failure of a
+    /// suspend episode is recorded, re-requests typically keep
+    /// failing the same way.
+    pub fn record_suspend_failed(&mut self, now: DateTime<Utc>, error: String) {
+        if self.suspend_failed {
+            return;
+        }
+        self.record(now, HistoryEventKind::SuspendFailed { error });
+    }
+
+    /// Write the history file if events were recorded since the last
+    /// save.
+    pub fn save_if_changed(&mut self) {
+        if self.unsaved_changes {
+            self.save();
+            self.unsaved_changes = false;
+        }
+    }
+
+    /// Track which stay-up rules are active, recording how long each
+    /// rule was blocking sleep once it becomes inactive.
+    pub fn update_active_stayup_rules(
+        &mut self,
+        now: DateTime<Utc>,
+        active_rules: &HashSet<RuleName>,
+    ) {
+        let finished_rules: Vec<RuleName> = self
+            .stayup_rules_active_since
+            .keys()
+            .filter(|rule| !active_rules.contains(*rule))
+            .cloned()
+            .collect();
+        for rule in finished_rules.into_iter() {
+            self.record_stayup_rule_active(rule, now);
+        }
+        for rule in active_rules.iter() {
+            if !self.stayup_rules_active_since.contains_key(rule) {
+                self.stayup_rules_active_since.insert(rule.clone(), now);
+            }
+        }
+    }
+
+    /// Record all currently active stay-up rules as finished, e.g.
+    /// when waketimed is terminating.
+    pub fn finish_active_stayup_rules(&mut self, now: DateTime<Utc>) {
+        self.update_active_stayup_rules(now, &HashSet::new());
+    }
+
+    fn record_stayup_rule_active(&mut self, rule: RuleName, now: DateTime<Utc>) {
+        if let Some(since) = self.stayup_rules_active_since.remove(&rule) {
+            let active_ms = (now - since).num_milliseconds().max(0) as u64;
+            self.record(now, HistoryEventKind::StayupRuleActive { rule, active_ms });
+        }
+    }
+
+    fn truncate(&mut self) {
+        let max_events = self.cfg.history_max_events as usize;
+        while self.events.len() > max_events {
+            self.events.pop_front();
+        }
+    }
+
+    fn save(&self) {
+        let history_file = match self.cfg.history_file() {
+            Some(path) => path,
+            None => return,
+        };
+        let result = serde_yaml::to_string(&self.events)
+            .map_err(AnyError::from)
+            .and_then(|content| files::write_atomically(&history_file, content.as_bytes()));
+        if let Err(e) = result {
+            warn!("Failed to save history: {:#}", e);
+        }
+    }
+}
+
+/// Load history events from a history file. A missing file means
+/// empty history.
+pub fn load_events<P: AsRef<Path>>(path: P) -> Result<Vec<HistoryEvent>, AnyError> {
+    let path = path.as_ref();
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read history file '{}'", path.display()))?;
+    if content.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    serde_yaml::from_str(&content)
+        .with_context(|| format!("Failed to parse history file '{}'", path.display()))
+}
+
+/// Compute statistics from a history file and format them as YAML,
+/// with days in the local time zone.
+pub fn stats_yaml<P: AsRef<Path>>(path: P) -> Result<String, AnyError> {
+    let events = load_events(path)?;
+    let stats = history::compute_stats(&events, Utc::now(), &Local);
+    Ok(serde_yaml::to_string(&stats)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{default_config, rule_name, temp_dir};
+    use chrono::TimeZone;
+
+    fn create_history_manager(test_name: &str, max_events: u64) -> HistoryManager {
+        let mut cfg = default_config();
+        cfg.state_dir = temp_dir(test_name).to_string_lossy().to_string();
+        cfg.history_max_events = max_events;
+        HistoryManager::new(Rc::new(cfg))
+    }
+
+    fn secs(secs: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(1_600_000_000 + secs, 0).unwrap()
+    }
+
+    #[test]
+    fn test_record_and_reload() -> Result<(), AnyError> {
+        let mut mgr = create_history_manager("history_record_and_reload", 3);
+        mgr.init()?;
+        mgr.record(secs(0), HistoryEventKind::Started);
+        mgr.record(secs(1), HistoryEventKind::SuspendRequested);
+        mgr.record(secs(2), HistoryEventKind::Suspending);
+        mgr.record(
+            secs(3),
+            HistoryEventKind::Resumed {
+                wake_reason: "usb".to_string(),
+            },
+        );
+        mgr.save_if_changed();
+
+        let history_file = mgr.cfg.history_file().expect("No history file.");
+        let events = load_events(history_file)?;
+        let kinds: Vec<HistoryEventKind> = events.into_iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                HistoryEventKind::SuspendRequested,
+                HistoryEventKind::Suspending,
+                HistoryEventKind::Resumed {
+                    wake_reason: "usb".to_string()
+                },
+            ]
+        );
+
+        let mut reloaded = HistoryManager::new(mgr.cfg.clone());
+        reloaded.init()?;
+        assert_eq!(reloaded.events, mgr.events);
+        Ok(())
+    }
+
+    #[test]
+    fn test_suspend_episodes() -> Result<(), AnyError> {
+        let mut mgr = create_history_manager("history_suspend_episodes", 100);
+        mgr.init()?;
+        // Suspend keeps failing, re-requests on each tick are one
+        // episode.
+        for i in 0..3 {
+            mgr.record_suspend_requested(secs(i));
+            mgr.record_suspend_failed(secs(i), "denied".to_string());
+        }
+        mgr.record(secs(5), HistoryEventKind::Suspending);
+        mgr.record_suspend_requested(secs(6));
+        let kinds: Vec<HistoryEventKind> = mgr.events.iter().map(|e| e.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                HistoryEventKind::SuspendRequested,
+                HistoryEventKind::SuspendFailed {
+                    error: "denied".to_string()
+                },
+                HistoryEventKind::Suspending,
+                HistoryEventKind::SuspendRequested,
+            ]
+        );
+
+        // Nothing is written until saved.
+        let history_file = mgr.cfg.history_file().expect("No history file.");
+        assert!(load_events(&history_file)?.is_empty());
+        mgr.save_if_changed();
+        assert_eq!(load_events(&history_file)?.len(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_stayup_rules() -> Result<(), AnyError> {
+        let mut mgr = create_history_manager("history_stayup_rules", 100);
+        mgr.init()?;
+        let rule_a = rule_name("rule_a");
+        let rule_b = rule_name("rule_b");
+
+        mgr.update_active_stayup_rules(secs(0), &HashSet::from([rule_a.clone()]));
+        mgr.update_active_stayup_rules(secs(5), &HashSet::from([rule_a.clone(), rule_b.clone()]));
+        assert!(mgr.events.is_empty());
+        mgr.update_active_stayup_rules(secs(30), &HashSet::from([rule_b.clone()]));
+        mgr.finish_active_stayup_rules(secs(65));
+        let kinds: Vec<HistoryEventKind> = mgr.events.iter().map(|e| e.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                HistoryEventKind::StayupRuleActive {
+                    rule: rule_a,
+                    active_ms: 30_000
+                },
+                HistoryEventKind::StayupRuleActive {
+                    rule: rule_b,
+                    active_ms: 60_000
+                },
+            ]
+        );
+        assert_eq!(mgr.events[1].time, secs(65));
+        Ok(())
+    }
+}
@@ -1,29 +1,38 @@
 pub(crate) mod chassis_check;
 mod config;
+pub(crate) mod dbus_api;
 // If we revive the idea of giving waketimed a dbus interface, core
 // will likely get moved into its own package.
 pub(crate) mod core;
 pub(crate) mod embedded_files;
 mod engine;
 pub(crate) mod files;
+pub(crate) mod history_manager;
 pub(crate) mod messages;
+pub(crate) mod persistent_state;
+pub(crate) mod rule_analysis;
+pub(crate) mod rule_fns;
 pub(crate) mod rule_manager;
 pub(crate) mod sleep_manager;
+pub(crate) mod systemd;
 #[cfg(test)]
 pub(crate) mod test_helpers;
 pub(crate) mod time;
+pub(crate) mod user_buses;
 pub(crate) mod var_creation_context;
 pub(crate) mod var_fns;
 pub(crate) mod var_manager;
+pub(crate) mod wake_reason;
 mod worker;
 
 use crate::config::Config;
 use crate::engine::Engine;
 use crate::messages::{EngineMsg, WorkerMsg};
-use anyhow::Error as AnyError;
+use anyhow::{anyhow, Error as AnyError};
 use log::{error, trace};
 use signal_hook::consts::signal::{SIGINT, SIGTERM};
 use signal_hook::iterator::Signals;
+use std::env;
 use std::rc::Rc;
 
 use std::thread::{self, JoinHandle};
@@ -35,6 +44,11 @@ fn main() -> Result<(), AnyError> {
     let cfg = config::load()?;
     setup_logger(&cfg);
     config::log_config(&cfg)?;
+    // Before spawning threads, as this modifies the environment.
+    time::set_local_time_zone(&cfg.time_zone);
+    if let Some(command) = env::args().nth(1) {
+        return run_command(&cfg, &command);
+    }
 
     let (engine_send, engine_recv) = unbounded_channel::<EngineMsg>();
     let (worker_send, worker_recv) = unbounded_channel::<WorkerMsg>();
@@ -60,6 +74,24 @@ fn setup_logger(cfg: &Config) {
     env_logger::builder().parse_filters(&cfg.log).init();
 }
 
+// One-shot query commands, running the daemon takes no arguments.
+fn run_command(cfg: &Config, command: &str) -> Result<(), AnyError> {
+    match command {
+        "history-stats" => {
+            let history_file = cfg
+                .history_file()
+                .ok_or_else(|| anyhow!("History is disabled, state_dir is empty."))?;
+            print!("{}", history_manager::stats_yaml(history_file)?);
+            Ok(())
+        }
+        "rule-functions-doc" => {
+            print!("{}", rule_fns::rule_fns_doc());
+            Ok(())
+        }
+        _ => Err(anyhow!("Unknown command '{}'.", command)),
+    }
+}
+
 fn main_thread_main(
     cfg: Config,
     mut engine_recv: UnboundedReceiver<EngineMsg>,
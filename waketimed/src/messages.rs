@@ -1,9 +1,13 @@
 use crate::core::vars::{VarDef, VarName, VarValue};
+use std::path::PathBuf;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq)]
 pub enum EngineMsg {
     PollVarsTick,
     ReturnVarPoll(VarName, Option<VarValue>),
+    // SuspendFailed(error)
+    SuspendFailed(String),
+    SuspendTick,
     SystemIsResuming,
     SystemIsSuspending,
     Terminate,
@@ -12,7 +16,15 @@ pub enum EngineMsg {
 #[derive(Debug, PartialEq, Eq)]
 pub enum WorkerMsg {
     CallVarPoll(VarName),
+    // EmitSleepApproaching(ms_until_suspend, notify_desktop)
+    EmitSleepApproaching(u64, bool),
+    // EmitSleepApproachingCancelled(notify_desktop)
+    EmitSleepApproachingCancelled(bool),
     LoadPollVarFns(VarDef),
+    // ServeControlInterface(history_file)
+    ServeControlInterface(Option<PathBuf>),
+    // ScheduleSuspendTick(ms_from_now)
+    ScheduleSuspendTick(u64),
     // SpawnPollVarInterval(ms)
     SpawnPollVarInterval(u64),
     // Suspend(test_mode)
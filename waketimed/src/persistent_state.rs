@@ -0,0 +1,192 @@
+use crate::core::vars::{VarName, VarValue};
+use crate::files;
+use anyhow::{anyhow, Context, Error as AnyError};
+use chrono::{DateTime, Utc};
+use log::{debug, warn};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const BOOT_ID_PATH: &str = "/proc/sys/kernel/random/boot_id";
+// Bump when the state file format changes incompatibly. State files
+// with a different version are ignored.
+pub const STATE_FORMAT_VERSION: u32 = 1;
+
+/// State which waketimed keeps across daemon restarts.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PersistentState {
+    pub version: u32,
+    // Timing info relative to the suspend clock is only meaningful
+    // within the same boot.
+    pub boot_id: String,
+    pub saved_at: DateTime<Utc>,
+    #[serde(default)]
+    pub sleep: PersistentSleepState,
+    #[serde(default)]
+    pub vars: HashMap<VarName, VarValue>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PersistentSleepState {
+    // Milliseconds on the suspend clock.
+    pub nearest_possible_suspend: u64,
+    pub wake_reason: String,
+}
+
+#[derive(Deserialize)]
+struct StateVersion {
+    version: u32,
+}
+
+impl PersistentState {
+    pub fn new(
+        sleep: PersistentSleepState,
+        vars: HashMap<VarName, VarValue>,
+    ) -> Result<Self, AnyError> {
+        Ok(Self {
+            version: STATE_FORMAT_VERSION,
+            boot_id: current_boot_id()?,
+            saved_at: Utc::now(),
+            sleep,
+            vars,
+        })
+    }
+
+    pub fn is_from_current_boot(&self) -> bool {
+        current_boot_id()
+            .map(|boot_id| boot_id == self.boot_id)
+            .unwrap_or(false)
+    }
+}
+
+/// Load persistent state from `path`. Missing, unreadable, corrupted
+/// and incompatible state files result in `None`. Corrupted files
+/// are moved aside so that they don't get in the way on next start.
+pub fn load<P: AsRef<Path>>(path: P) -> Option<PersistentState> {
+    let path = path.as_ref();
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) => {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                warn!("Failed to read state file '{}': {}", path.display(), e);
+            } else {
+                debug!("State file '{}' does not exist.", path.display());
+            }
+            return None;
+        }
+    };
+
+    match parse(&content) {
+        Ok(Some(state)) => Some(state),
+        Ok(None) => None,
+        Err(e) => {
+            warn!(
+                "State file '{}' is corrupted, ignoring it: {:#}",
+                path.display(),
+                e
+            );
+            let corrupt_path = path_with_suffix(path, ".corrupt");
+            if let Err(e) = fs::rename(path, &corrupt_path) {
+                warn!(
+                    "Failed to move corrupted state file to '{}': {}",
+                    corrupt_path.display(),
+                    e
+                );
+            }
+            None
+        }
+    }
+}
+
+/// Save persistent state to `path` atomically.
+pub fn save<P: AsRef<Path>>(path: P, state: &PersistentState) -> Result<(), AnyError> {
+    let content = serde_yaml::to_string(state)?;
+    files::write_atomically(&path, content.as_bytes())?;
+    debug!("Saved state to '{}'.", path.as_ref().display());
+    Ok(())
+}
+
+fn parse(content: &str) -> Result<Option<PersistentState>, AnyError> {
+    let version: StateVersion = serde_yaml::from_str(content)?;
+    if version.version != STATE_FORMAT_VERSION {
+        warn!(
+            "State file has format version {}, expected {}. Ignoring it.",
+            version.version, STATE_FORMAT_VERSION
+        );
+        return Ok(None);
+    }
+    Ok(Some(serde_yaml::from_str(content)?))
+}
+
+fn current_boot_id() -> Result<String, AnyError> {
+    let boot_id = fs::read_to_string(BOOT_ID_PATH)
+        .with_context(|| format!("Failed to read boot ID from '{BOOT_ID_PATH}'"))?;
+    let boot_id = boot_id.trim();
+    if boot_id.is_empty() {
+        return Err(anyhow!("Boot ID is empty."));
+    }
+    Ok(boot_id.to_string())
+}
+
+fn path_with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path_string = path.as_os_str().to_os_string();
+    path_string.push(suffix);
+    PathBuf::from(path_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{temp_dir, var_name};
+
+    fn create_state() -> PersistentState {
+        let sleep = PersistentSleepState {
+            nearest_possible_suspend: 123_456,
+            wake_reason: "modem".to_string(),
+        };
+        let vars = HashMap::from([
+            (var_name("test_bool"), VarValue::Bool(true)),
+            (var_name("test_int"), VarValue::Int(42)),
+            (var_name("test_float"), VarValue::Float(2.0)),
+            (var_name("test_string"), VarValue::String("abc".to_string())),
+        ]);
+        PersistentState::new(sleep, vars).expect("Failed to create PersistentState.")
+    }
+
+    #[test]
+    fn test_save_and_load() -> Result<(), AnyError> {
+        let dir = temp_dir("persistent_state_save_and_load");
+        let path = dir.join("state.yaml");
+        assert_eq!(load(&path), None);
+
+        let state = create_state();
+        save(&path, &state)?;
+        let loaded = load(&path).expect("Failed to load saved state.");
+        assert_eq!(loaded, state);
+        assert!(loaded.is_from_current_boot());
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_corrupted() -> Result<(), AnyError> {
+        let dir = temp_dir("persistent_state_load_corrupted");
+        let path = dir.join("state.yaml");
+        fs::write(&path, "version: 1\nboot_id: [unterminated")?;
+        assert_eq!(load(&path), None);
+        assert!(!path.exists());
+        assert!(dir.join("state.yaml.corrupt").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_other_version() -> Result<(), AnyError> {
+        let dir = temp_dir("persistent_state_load_other_version");
+        let path = dir.join("state.yaml");
+        fs::write(&path, "version: 999\nsomething_new: true\n")?;
+        assert_eq!(load(&path), None);
+        // Files from other versions aren't corrupted, keep them.
+        assert!(path.exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,307 @@
+use crate::core::vars::{VarDataType, VarDef, VarName};
+use crate::rule_fns::{self, VarRefs};
+use rhai::{ASTNode, Array as RhaiArray, Expr, FnCallExpr, Stmt, AST as RhaiAST};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+const IS_DEF_VAR_FN: &str = "is_def_var";
+const NOT_OPERATOR: &str = "!";
+
+/// What a rule script refers to, found by walking its AST.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ScriptRefs {
+    // Identifiers read as vars, excluding ones the script defines.
+    pub vars: BTreeSet<String>,
+    // Var names passed as strings to rule functions. These may be
+    // undefined, the functions handle that.
+    pub named_vars: BTreeSet<String>,
+    // Var names passed to is_def_var, the script guards their use.
+    pub guarded_vars: BTreeSet<String>,
+    // Category names passed as strings to rule functions.
+    pub categories: BTreeSet<String>,
+    // Vars used directly where a bool is expected.
+    pub bool_vars: BTreeSet<String>,
+    // The script calls functions whose result changes as time passes.
+    pub time_dependent: bool,
+    // The script refers to vars by names which aren't constant, so
+    // its dependencies can't be known.
+    pub dynamic_refs: bool,
+}
+
+/// Inputs which a rule's value depends on.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RuleDeps {
+    pub vars: HashSet<VarName>,
+    // Value can change as time passes, even with no var changing.
+    pub time_dependent: bool,
+    // Vars the value depends on are not known statically.
+    pub dynamic_refs: bool,
+}
+
+impl RuleDeps {
+    /// Whether the rule value may differ from the previous evaluation.
+    pub fn affected_by(&self, changed_vars: &HashSet<VarName>) -> bool {
+        self.time_dependent || self.dynamic_refs || !self.vars.is_disjoint(changed_vars)
+    }
+}
+
+impl ScriptRefs {
+    pub fn rule_deps(&self, category_vars: &HashMap<VarName, HashSet<VarName>>) -> RuleDeps {
+        RuleDeps {
+            vars: self.var_deps(category_vars),
+            time_dependent: self.time_dependent,
+            dynamic_refs: self.dynamic_refs,
+        }
+    }
+
+    /// Vars which can influence the script result. Names which aren't
+    /// valid var names are skipped.
+    fn var_deps(&self, category_vars: &HashMap<VarName, HashSet<VarName>>) -> HashSet<VarName> {
+        let mut deps: HashSet<VarName> = self
+            .vars
+            .iter()
+            .chain(self.named_vars.iter())
+            .chain(self.guarded_vars.iter())
+            .chain(self.categories.iter())
+            .filter_map(|name| VarName::try_from(name.clone()).ok())
+            .collect();
+        for category in self.categories.iter() {
+            if let Some(members) = VarName::try_from(category.clone())
+                .ok()
+                .and_then(|category| category_vars.get(&category))
+            {
+                deps.extend(members.iter().cloned());
+            }
+        }
+        deps
+    }
+}
+
+/// Collect references of a rule script.
+pub fn script_refs(ast: &RhaiAST) -> ScriptRefs {
+    let mut refs = ScriptRefs::default();
+    let mut locals = HashSet::new();
+    ast.walk(&mut |path: &[ASTNode]| {
+        match path.last() {
+            Some(ASTNode::Expr(Expr::Variable(var, ..))) => {
+                refs.vars.insert(var.3.to_string());
+            }
+            Some(ASTNode::Expr(Expr::FnCall(call, ..)))
+            | Some(ASTNode::Stmt(Stmt::FnCall(call, ..))) => {
+                collect_fn_call_refs(call, &mut refs);
+            }
+            Some(ASTNode::Expr(Expr::And(operands, ..)))
+            | Some(ASTNode::Expr(Expr::Or(operands, ..))) => {
+                collect_bool_var(&operands.lhs, &mut refs);
+                collect_bool_var(&operands.rhs, &mut refs);
+            }
+            Some(ASTNode::Stmt(Stmt::Var(var, ..))) => {
+                locals.insert(var.0.name.to_string());
+            }
+            Some(ASTNode::Stmt(Stmt::For(for_loop, ..))) => {
+                locals.insert(for_loop.0.name.to_string());
+                locals.insert(for_loop.1.name.to_string());
+            }
+            _ => {}
+        }
+        true
+    });
+    // The value of the last statement is the result of the script.
+    if let Some(Stmt::Expr(expr)) = ast.statements().last() {
+        collect_bool_var(expr, &mut refs);
+    }
+    refs.vars.retain(|name| !locals.contains(name));
+    refs.bool_vars.retain(|name| !locals.contains(name));
+    refs
+}
+
+/// Check script references against the loaded var defs. Returns
+/// descriptions of problems found.
+pub fn check_script_refs(
+    refs: &ScriptRefs,
+    var_defs: &HashMap<VarName, VarDef>,
+    category_vars: &HashMap<VarName, HashSet<VarName>>,
+) -> Vec<String> {
+    let var_def = |name: &str| {
+        VarName::try_from(name.to_string())
+            .ok()
+            .and_then(|name| var_defs.get(&name))
+    };
+    let mut issues = Vec::new();
+    for name in refs.vars.iter() {
+        if var_def(name).is_none() && !refs.guarded_vars.contains(name) {
+            issues.push(format!("uses undefined var '{}'", name));
+        }
+    }
+    for name in refs.categories.iter() {
+        let defined = VarName::try_from(name.clone())
+            .map(|name| category_vars.contains_key(&name))
+            .unwrap_or(false);
+        if !defined {
+            issues.push(format!("refers to undefined category '{}'", name));
+        }
+    }
+    for name in refs.bool_vars.iter() {
+        if let Some(def) = var_def(name) {
+            if def.data_type != VarDataType::Bool {
+                issues.push(format!(
+                    "uses var '{}' of data type '{:?}' as bool",
+                    name, def.data_type
+                ));
+            }
+        }
+    }
+    issues
+}
+
+fn collect_fn_call_refs(call: &FnCallExpr, refs: &mut ScriptRefs) {
+    let name = call.name.as_str();
+    let first_arg = call.args.first();
+    if name == IS_DEF_VAR_FN {
+        match first_arg {
+            Some(Expr::StringConstant(var, ..)) => {
+                refs.guarded_vars.insert(var.to_string());
+            }
+            _ => refs.dynamic_refs = true,
+        }
+        return;
+    }
+    if name == NOT_OPERATOR && call.args.len() == 1 {
+        if let Some(arg) = first_arg {
+            collect_bool_var(arg, refs);
+        }
+        return;
+    }
+    refs.time_dependent |= rule_fns::is_time_dependent(name);
+    match (rule_fns::var_refs(name), first_arg) {
+        (VarRefs::Name, Some(Expr::StringConstant(var, ..))) => {
+            refs.named_vars.insert(var.to_string());
+        }
+        (VarRefs::Names, Some(Expr::Array(items, ..))) => {
+            for item in items.iter() {
+                match item {
+                    Expr::StringConstant(var, ..) => {
+                        refs.named_vars.insert(var.to_string());
+                    }
+                    _ => refs.dynamic_refs = true,
+                }
+            }
+        }
+        // Arrays of constants get folded into a single constant.
+        (VarRefs::Names, Some(Expr::DynamicConstant(value, ..))) => {
+            if let Some(items) = value.read_lock::<RhaiArray>() {
+                for item in items.iter() {
+                    if let Ok(var) = item.clone().into_immutable_string() {
+                        refs.named_vars.insert(var.to_string());
+                    }
+                }
+            }
+        }
+        (VarRefs::Category, Some(Expr::StringConstant(category, ..))) => {
+            refs.categories.insert(category.to_string());
+        }
+        (VarRefs::None, _) => {}
+        _ => refs.dynamic_refs = true,
+    }
+}
+
+fn collect_bool_var(expr: &Expr, refs: &mut ScriptRefs) {
+    if let Expr::Variable(var, ..) = expr {
+        refs.bool_vars.insert(var.3.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::vars::{BuiltinPollDef, VarKind};
+    use crate::test_helpers::var_name;
+    use rhai::Engine as RhaiEngine;
+
+    fn refs(script: &str) -> ScriptRefs {
+        let ast = RhaiEngine::new()
+            .compile(script)
+            .expect("Failed to compile script.");
+        script_refs(&ast)
+    }
+
+    fn names(names: &[&str]) -> BTreeSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_script_refs() {
+        let r = refs("test_a && !test_b || test_int > 5");
+        assert_eq!(r.vars, names(&["test_a", "test_b", "test_int"]));
+        assert_eq!(r.bool_vars, names(&["test_a", "test_b"]));
+        assert!(!r.time_dependent);
+
+        let r = refs("is_def_var(\"test_opt\") && test_opt");
+        assert_eq!(r.vars, names(&["test_opt"]));
+        assert_eq!(r.guarded_vars, names(&["test_opt"]));
+
+        let r = refs(
+            "let limit = minutes(5); \
+             since_changed(\"test_a\") > limit \
+             && any_true([\"test_b\", \"test_c\"]) \
+             && count_true(\"test_category\") > 1",
+        );
+        assert_eq!(r.vars, BTreeSet::new());
+        assert_eq!(r.named_vars, names(&["test_a", "test_b", "test_c"]));
+        assert_eq!(r.categories, names(&["test_category"]));
+        assert!(r.time_dependent);
+
+        let r = refs("test_a");
+        assert_eq!(r.bool_vars, names(&["test_a"]));
+        assert!(!r.dynamic_refs);
+
+        let r = refs("let name = \"test_a\"; is_true(name)");
+        assert!(r.dynamic_refs);
+    }
+
+    #[test]
+    fn test_check_script_refs() {
+        let def = |data_type: VarDataType| VarDef {
+            name: None,
+            data_type,
+            categories: vec![],
+            kind: VarKind::BuiltinPoll(BuiltinPollDef {
+                builtin_name: "const_bool".to_string(),
+                params: HashMap::new(),
+            }),
+        };
+        let var_defs: HashMap<VarName, VarDef> = [
+            (var_name("test_a"), def(VarDataType::Bool)),
+            (var_name("test_int"), def(VarDataType::Int)),
+        ]
+        .into();
+        let category_vars: HashMap<VarName, HashSet<VarName>> =
+            [(var_name("test_category"), [var_name("test_a")].into())].into();
+        let check = |script: &str| check_script_refs(&refs(script), &var_defs, &category_vars);
+
+        assert!(check("test_a && test_int > 1 && count_true(\"test_category\") > 0").is_empty());
+        assert!(check("is_def_var(\"test_opt\") && test_opt").is_empty());
+        assert_eq!(
+            check("test_missing"),
+            vec!["uses undefined var 'test_missing'"]
+        );
+        assert!(check("is_true(\"test_missing\")").is_empty());
+        assert!(check("var_or(\"test_missing\", 100) > 50").is_empty());
+        assert_eq!(
+            check("count_true(\"test_missing\") > 0"),
+            vec!["refers to undefined category 'test_missing'"]
+        );
+        assert_eq!(
+            check("test_a && test_int"),
+            vec!["uses var 'test_int' of data type 'Int' as bool"]
+        );
+
+        let deps = refs("test_a || count_true(\"test_category\") > 0").var_deps(&category_vars);
+        assert_eq!(deps, [var_name("test_a"), var_name("test_category")].into());
+
+        let deps = refs("test_a").rule_deps(&category_vars);
+        assert!(deps.affected_by(&[var_name("test_a")].into()));
+        assert!(!deps.affected_by(&[var_name("test_int")].into()));
+        let deps = refs("time_between(\"22:00\", \"06:00\")").rule_deps(&category_vars);
+        assert!(deps.affected_by(&HashSet::new()));
+    }
+}
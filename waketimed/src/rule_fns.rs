@@ -0,0 +1,445 @@
+use crate::core::vars::{VarName, VarValue};
+use chrono::{NaiveTime, Timelike};
+use rhai::{
+    Array as RhaiArray, Dynamic as RhaiDynamic, Engine as RhaiEngine,
+    EvalAltResult as RhaiEvalAltResult,
+};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::rc::Rc;
+use std::time::Duration;
+
+type RhaiResult<T> = Result<T, Box<RhaiEvalAltResult>>;
+pub type RuleFnsContextRef = Rc<RefCell<RuleFnsContext>>;
+
+/// Clock readings for an evaluation of rules. Taken by the caller, so
+/// that rule evaluation itself doesn't depend on clocks.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EvalTime {
+    // Suspend clock time.
+    pub now: Duration,
+    // Wall-clock time.
+    pub unix_time_ms: i64,
+    pub local_time: NaiveTime,
+}
+
+/// State which the rule script functions read. It is refreshed by
+/// RuleManager before each evaluation of rules.
+#[derive(Clone, Debug, Default)]
+pub struct RuleFnsContext {
+    pub vars: HashMap<VarName, VarValue>,
+    pub category_vars: HashMap<VarName, HashSet<VarName>>,
+    // Suspend clock times of the last change of each var.
+    pub var_changed_at: HashMap<VarName, Duration>,
+    // Suspend clock time when waketimed started.
+    pub start_time: Duration,
+    // Suspend clock time of the current evaluation.
+    pub now: Duration,
+    // Wall-clock time of the current evaluation.
+    pub unix_time_ms: i64,
+    pub local_time: NaiveTime,
+}
+
+impl RuleFnsContext {
+    /// Take over new values of changed vars, recording the time of
+    /// the change.
+    pub fn update_vars(&mut self, vars: &HashMap<VarName, VarValue>, changed: &HashSet<VarName>) {
+        for var_name in changed.iter() {
+            if let Some(value) = vars.get(var_name) {
+                self.vars.insert(var_name.clone(), value.clone());
+                self.var_changed_at.insert(var_name.clone(), self.now);
+            }
+        }
+    }
+
+    fn var(&self, name: &str) -> Option<&VarValue> {
+        VarName::try_from(name.to_string())
+            .ok()
+            .and_then(|name| self.vars.get(&name))
+    }
+
+    fn is_true(&self, name: &str) -> bool {
+        matches!(self.var(name), Some(VarValue::Bool(true)))
+    }
+
+    fn since_changed(&self, name: &str) -> i64 {
+        let changed_at = VarName::try_from(name.to_string())
+            .ok()
+            .and_then(|name| self.var_changed_at.get(&name).copied())
+            .unwrap_or(self.start_time);
+        duration_ms(self.now.saturating_sub(changed_at))
+    }
+
+    fn any_true(&self, names: RhaiArray) -> RhaiResult<bool> {
+        let mut result = false;
+        for name in names.into_iter() {
+            let name = name
+                .into_immutable_string()
+                .map_err(|t| format!("any_true expects an array of strings, got '{}'.", t))?;
+            result = result || self.is_true(&name);
+        }
+        Ok(result)
+    }
+
+    fn count_true(&self, category: &str) -> RhaiResult<i64> {
+        let members = VarName::try_from(category.to_string())
+            .ok()
+            .and_then(|name| self.category_vars.get(&name))
+            .ok_or_else(|| format!("Unknown category '{}'.", category))?;
+        Ok(members
+            .iter()
+            .filter(|name| self.is_true(name.as_ref()))
+            .count() as i64)
+    }
+
+    fn time_between(&self, from: &str, to: &str) -> RhaiResult<bool> {
+        let from = parse_time_of_day(from)?;
+        let to = parse_time_of_day(to)?;
+        let now = self
+            .local_time
+            .with_nanosecond(0)
+            .unwrap_or(self.local_time);
+        if from <= to {
+            Ok(from <= now && now < to)
+        } else {
+            // The range crosses midnight.
+            Ok(from <= now || now < to)
+        }
+    }
+}
+
+/// How a function refers to vars via its first argument, for static
+/// analysis of rule scripts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VarRefs {
+    None,
+    // A var name string.
+    Name,
+    // An array of var name strings.
+    Names,
+    // A category name string.
+    Category,
+}
+
+struct RuleFnDef {
+    name: &'static str,
+    signature: &'static str,
+    description: &'static str,
+    var_refs: VarRefs,
+    // Result can change without any var changing.
+    time_dependent: bool,
+    // Shown in the docs. Tests check that it evaluates to true at
+    // 23:00 local time.
+    example: &'static str,
+    register: fn(&mut RhaiEngine, &RuleFnsContextRef),
+}
+
+const RULE_FNS: &[RuleFnDef] = &[
+    RuleFnDef {
+        name: "seconds",
+        signature: "seconds(n: int) -> int",
+        description: "Duration of `n` seconds in milliseconds.",
+        var_refs: VarRefs::None,
+        time_dependent: false,
+        example: "seconds(90) == 90000",
+        register: |engine, _| {
+            engine.register_fn("seconds", |n: i64| n.saturating_mul(1_000));
+        },
+    },
+    RuleFnDef {
+        name: "minutes",
+        signature: "minutes(n: int) -> int",
+        description: "Duration of `n` minutes in milliseconds.",
+        var_refs: VarRefs::None,
+        time_dependent: false,
+        example: "minutes(2) == seconds(120)",
+        register: |engine, _| {
+            engine.register_fn("minutes", |n: i64| n.saturating_mul(60_000));
+        },
+    },
+    RuleFnDef {
+        name: "hours",
+        signature: "hours(n: int) -> int",
+        description: "Duration of `n` hours in milliseconds.",
+        var_refs: VarRefs::None,
+        time_dependent: false,
+        example: "hours(1) == minutes(60)",
+        register: |engine, _| {
+            engine.register_fn("hours", |n: i64| n.saturating_mul(3_600_000));
+        },
+    },
+    RuleFnDef {
+        name: "now",
+        signature: "now() -> int",
+        description: "Current wall-clock time as milliseconds since the Unix epoch.",
+        var_refs: VarRefs::None,
+        time_dependent: true,
+        example: "now() > 0",
+        register: |engine, ctx| {
+            let ctx = ctx.clone();
+            engine.register_fn("now", move || ctx.borrow().unix_time_ms);
+        },
+    },
+    RuleFnDef {
+        name: "since_changed",
+        signature: "since_changed(var: string) -> int",
+        description: "Milliseconds since the value of variable `var` last changed. \
+                      If the variable never had a value, milliseconds since waketimed \
+                      started. Includes time spent suspended.",
+        var_refs: VarRefs::Name,
+        time_dependent: true,
+        example: "since_changed(\"wtd_charging\") >= 0",
+        register: |engine, ctx| {
+            let ctx = ctx.clone();
+            engine.register_fn("since_changed", move |var: &str| {
+                ctx.borrow().since_changed(var)
+            });
+        },
+    },
+    RuleFnDef {
+        name: "var_or",
+        signature: "var_or(var: string, default: any) -> any",
+        description: "Value of variable `var`, or `default` if the variable is \
+                      undefined.",
+        var_refs: VarRefs::Name,
+        time_dependent: false,
+        example: "var_or(\"wtd_battery_percent\", 100) >= 0",
+        register: |engine, ctx| {
+            let ctx = ctx.clone();
+            engine.register_fn("var_or", move |var: &str, default: RhaiDynamic| {
+                ctx.borrow().var(var).map(to_dynamic).unwrap_or(default)
+            });
+        },
+    },
+    RuleFnDef {
+        name: "is_true",
+        signature: "is_true(var: string) -> bool",
+        description: "`true` if variable `var` is defined and `true`. Undefined and \
+                      non-bool variables give `false`.",
+        var_refs: VarRefs::Name,
+        time_dependent: false,
+        example: "!is_true(\"nonexistent_var\")",
+        register: |engine, ctx| {
+            let ctx = ctx.clone();
+            engine.register_fn("is_true", move |var: &str| ctx.borrow().is_true(var));
+        },
+    },
+    RuleFnDef {
+        name: "any_true",
+        signature: "any_true(vars: array) -> bool",
+        description: "`true` if any of the variables named in `vars` is `true`, \
+                      with the same semantics as `is_true`.",
+        var_refs: VarRefs::Names,
+        time_dependent: false,
+        example: "!any_true([\"nonexistent_var\"])",
+        register: |engine, ctx| {
+            let ctx = ctx.clone();
+            engine.register_fn("any_true", move |vars: RhaiArray| {
+                ctx.borrow().any_true(vars)
+            });
+        },
+    },
+    RuleFnDef {
+        name: "count_true",
+        signature: "count_true(category: string) -> int",
+        description: "Number of variables in category `category` which are `true`. \
+                      Fails if no variable defines the category.",
+        var_refs: VarRefs::Category,
+        time_dependent: false,
+        example: "count_true(\"wtd_user_busy\") >= 0",
+        register: |engine, ctx| {
+            let ctx = ctx.clone();
+            engine.register_fn("count_true", move |category: &str| {
+                ctx.borrow().count_true(category)
+            });
+        },
+    },
+    RuleFnDef {
+        name: "time_between",
+        signature: "time_between(from: string, to: string) -> bool",
+        description: "`true` if the local time of day is at or after `from` and \
+                      before `to`, both given as `\"HH:MM\"` or `\"HH:MM:SS\"`. The \
+                      range may cross midnight.",
+        var_refs: VarRefs::None,
+        time_dependent: true,
+        example: "time_between(\"22:00\", \"06:00\")",
+        register: |engine, ctx| {
+            let ctx = ctx.clone();
+            engine.register_fn("time_between", move |from: &str, to: &str| {
+                ctx.borrow().time_between(from, to)
+            });
+        },
+    },
+];
+
+/// Register all rule script functions with the script engine.
+pub fn register_rule_fns(engine: &mut RhaiEngine, ctx: &RuleFnsContextRef) {
+    for def in RULE_FNS.iter() {
+        (def.register)(engine, ctx);
+    }
+}
+
+/// How the rule script function `name` refers to vars.
+pub fn var_refs(name: &str) -> VarRefs {
+    find_rule_fn(name)
+        .map(|def| def.var_refs)
+        .unwrap_or(VarRefs::None)
+}
+
+/// Whether the rule script function `name` can return a different
+/// result with the same vars, as time passes.
+pub fn is_time_dependent(name: &str) -> bool {
+    find_rule_fn(name)
+        .map(|def| def.time_dependent)
+        .unwrap_or(false)
+}
+
+fn find_rule_fn(name: &str) -> Option<&'static RuleFnDef> {
+    RULE_FNS.iter().find(|def| def.name == name)
+}
+
+/// Markdown documentation of the rule script functions.
+pub fn rule_fns_doc() -> String {
+    let mut doc = String::from(
+        "[parent page](index.md)\n\n\
+         # Rule functions\n\n\
+         <!-- Generated by `waketimed rule-functions-doc`, do not edit. -->\n\n\
+         Besides variables, rule scripts can use these functions. Durations\n\
+         are in milliseconds. Functions taking variable names as strings\n\
+         can safely refer to undefined variables, as does the Rhai builtin\n\
+         `is_def_var(var)`.\n",
+    );
+    for def in RULE_FNS.iter() {
+        write!(
+            doc,
+            "\n* `{}` – {}\n\n  Example: `{}`\n",
+            def.signature, def.description, def.example
+        )
+        .expect("Failed to write to String.");
+    }
+    doc
+}
+
+pub fn to_dynamic(value: &VarValue) -> RhaiDynamic {
+    match value {
+        VarValue::Bool(v) => RhaiDynamic::from_bool(*v),
+        VarValue::Int(v) => RhaiDynamic::from_int(*v),
+        VarValue::Float(v) => RhaiDynamic::from_float(*v),
+        VarValue::String(v) => RhaiDynamic::from(v.clone()),
+    }
+}
+
+fn parse_time_of_day(time: &str) -> RhaiResult<NaiveTime> {
+    NaiveTime::parse_from_str(time, "%H:%M:%S")
+        .or_else(|_| NaiveTime::parse_from_str(time, "%H:%M"))
+        .map_err(|_| format!("Invalid time of day '{}', expected \"HH:MM\".", time).into())
+}
+
+fn duration_ms(duration: Duration) -> i64 {
+    duration.as_millis().try_into().unwrap_or(i64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::var_name;
+
+    fn engine_with_context() -> (RhaiEngine, RuleFnsContextRef) {
+        let ctx = Rc::new(RefCell::new(RuleFnsContext::default()));
+        let mut engine = RhaiEngine::new();
+        register_rule_fns(&mut engine, &ctx);
+        (engine, ctx)
+    }
+
+    #[test]
+    fn test_examples() {
+        let (engine, ctx) = engine_with_context();
+        {
+            let mut ctx = ctx.borrow_mut();
+            ctx.category_vars
+                .insert(var_name("wtd_user_busy"), HashSet::new());
+            ctx.unix_time_ms = 1_600_000_000_000;
+            ctx.local_time = NaiveTime::from_hms_opt(23, 0, 0).unwrap();
+        }
+        for def in RULE_FNS.iter() {
+            assert!(def.signature.starts_with(&format!("{}(", def.name)));
+            assert_eq!(
+                engine.eval::<bool>(def.example).ok(),
+                Some(true),
+                "Example failed: {}",
+                def.example
+            );
+        }
+    }
+
+    #[test]
+    fn test_var_fns() {
+        let (engine, ctx) = engine_with_context();
+        {
+            let mut ctx = ctx.borrow_mut();
+            ctx.category_vars.insert(
+                var_name("test_category"),
+                [var_name("test_a"), var_name("test_b"), var_name("test_c")].into(),
+            );
+            ctx.now = Duration::from_secs(10);
+            let mut vars: HashMap<VarName, VarValue> = [
+                (var_name("test_a"), VarValue::Bool(true)),
+                (var_name("test_b"), VarValue::Bool(false)),
+                (var_name("test_int"), VarValue::Int(5)),
+            ]
+            .into();
+            ctx.update_vars(&vars, &vars.keys().cloned().collect());
+            ctx.now = Duration::from_secs(15);
+            vars.insert(var_name("test_b"), VarValue::Bool(true));
+            ctx.update_vars(&vars, &[var_name("test_b")].into());
+            ctx.now = Duration::from_secs(20);
+        }
+        let eval = |script: &str| engine.eval::<RhaiDynamic>(script).unwrap();
+        assert_eq!(eval("since_changed(\"test_a\")").as_int(), Ok(10_000));
+        assert_eq!(eval("since_changed(\"test_b\")").as_int(), Ok(5_000));
+        assert_eq!(eval("since_changed(\"test_c\")").as_int(), Ok(20_000));
+        assert_eq!(eval("var_or(\"test_int\", 0)").as_int(), Ok(5));
+        assert_eq!(eval("var_or(\"test_c\", 7)").as_int(), Ok(7));
+        assert_eq!(eval("count_true(\"test_category\")").as_int(), Ok(2));
+        assert_eq!(
+            eval("any_true([\"test_c\", \"test_b\"])").as_bool(),
+            Ok(true)
+        );
+        assert!(engine.eval::<bool>("any_true([1])").is_err());
+        assert!(engine.eval::<i64>("count_true(\"test_a\")").is_err());
+    }
+
+    #[test]
+    fn test_time_between() {
+        let (engine, ctx) = engine_with_context();
+        let at = |h: u32, m: u32| {
+            ctx.borrow_mut().local_time = NaiveTime::from_hms_milli_opt(h, m, 0, 500).unwrap();
+        };
+        let eval = |script: &str| engine.eval::<bool>(script).unwrap();
+        at(23, 0);
+        assert!(eval("time_between(\"22:00\", \"06:00\")"));
+        assert!(!eval("time_between(\"08:00\", \"22:00\")"));
+        at(6, 0);
+        assert!(!eval("time_between(\"22:00\", \"06:00\")"));
+        assert!(eval("time_between(\"05:59:59\", \"06:00:01\")"));
+        at(12, 0);
+        assert!(!eval("time_between(\"12:00\", \"12:00\")"));
+        assert!(engine
+            .eval::<bool>("time_between(\"25:00\", \"06:00\")")
+            .is_err());
+    }
+
+    #[test]
+    fn test_rule_fns_doc_up_to_date() {
+        let doc_path = format!(
+            "{}/../docs/user/variables-and-rules/rule-functions.md",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let doc = std::fs::read_to_string(doc_path).expect("Failed to read rule functions doc.");
+        assert!(
+            doc == rule_fns_doc(),
+            "Rule functions doc is outdated, regenerate it with `waketimed rule-functions-doc`."
+        );
+    }
+}
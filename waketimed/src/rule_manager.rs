@@ -1,36 +1,63 @@
-use crate::config::Config;
+use crate::config::{Config, RuleCheckStrictness};
 use crate::core::rules::{RuleDef, RuleKind, RuleName};
-use crate::core::vars::{VarName, VarValue};
+use crate::core::vars::{VarDef, VarName, VarValue};
 use crate::files;
-use anyhow::Error as AnyError;
-use log::{debug, trace, warn};
-use rhai::{Dynamic as RhaiDynamic, Engine as RhaiEngine, Scope as RhaiScope, AST as RhaiAST};
+use crate::rule_analysis::{self, RuleDeps};
+use crate::rule_fns::{self, EvalTime, RuleFnsContext, RuleFnsContextRef};
+use anyhow::{anyhow, Error as AnyError};
+use log::{debug, error, info, trace, warn};
+use rhai::{
+    Dynamic as RhaiDynamic, Engine as RhaiEngine, EvalAltResult as RhaiEvalAltResult,
+    Scope as RhaiScope, AST as RhaiAST,
+};
+use std::cell::RefCell;
 use std::rc::Rc;
+use std::time::Duration;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 pub struct RuleManager {
     cfg: Rc<Config>,
     script_engine: RhaiEngine,
+    script_fns_context: RuleFnsContextRef,
     script_scope: RhaiScope<'static>,
     stayup_defs: HashMap<RuleName, RuleDef>,
     stayup_value_asts: HashMap<RuleName, RhaiAST>,
+    stayup_deps: HashMap<RuleName, RuleDeps>,
     stayup_values: HashMap<RuleName, bool>,
 }
 
 impl RuleManager {
     pub fn new(cfg: Rc<Config>) -> Self {
+        let script_fns_context = Rc::new(RefCell::new(RuleFnsContext::default()));
+        let mut script_engine = RhaiEngine::new();
+        Self::restrict_script_engine(&mut script_engine, &cfg);
+        rule_fns::register_rule_fns(&mut script_engine, &script_fns_context);
         Self {
             cfg,
-            script_engine: RhaiEngine::new(),
+            script_engine,
+            script_fns_context,
             script_scope: RhaiScope::new(),
             stayup_defs: HashMap::new(),
             stayup_value_asts: HashMap::new(),
+            stayup_deps: HashMap::new(),
             stayup_values: HashMap::new(),
         }
     }
 
-    pub fn init(&mut self) -> Result<(), AnyError> {
+    /// Load and check rule defs. The start time is a suspend clock
+    /// time, which rule functions measure var changes against.
+    pub fn init(
+        &mut self,
+        var_defs: &HashMap<VarName, VarDef>,
+        category_vars: HashMap<VarName, HashSet<VarName>>,
+        start_time: Duration,
+    ) -> Result<(), AnyError> {
+        {
+            let mut context = self.script_fns_context.borrow_mut();
+            context.category_vars = category_vars;
+            context.start_time = start_time;
+        }
         let rule_defs = files::load_rule_defs(&self.cfg)?;
         for (rule_name, rule_def) in rule_defs.into_iter() {
             use RuleKind::*;
@@ -42,43 +69,90 @@ impl RuleManager {
         }
 
         self.compile_stayup_value_asts()?;
+        self.analyze_stayup_value_asts(var_defs)?;
         Ok(())
     }
 
-    pub fn reset_script_scope(&mut self, vars: &HashMap<VarName, VarValue>) {
-        let mut scope = RhaiScope::new();
-        for (var_name, var_value) in vars.iter() {
-            use VarValue::*;
-            match var_value {
-                Bool(v) => {
-                    scope.push_constant_dynamic(var_name.as_ref(), RhaiDynamic::from_bool(*v));
-                }
+    /// Update script scope entries of changed vars.
+    pub fn update_script_scope(
+        &mut self,
+        vars: &HashMap<VarName, VarValue>,
+        changed_vars: &HashSet<VarName>,
+        eval_time: &EvalTime,
+    ) {
+        {
+            let mut context = self.script_fns_context.borrow_mut();
+            context.now = eval_time.now;
+            context.unix_time_ms = eval_time.unix_time_ms;
+            context.local_time = eval_time.local_time;
+            context.update_vars(vars, changed_vars);
+        }
+        for var_name in changed_vars.iter() {
+            if let Some(var_value) = vars.get(var_name) {
+                // Vars are constants in the scope so that scripts can't
+                // modify them, and constants can only be replaced.
+                let _old_value: Option<RhaiDynamic> = self.script_scope.remove(var_name.as_ref());
+                self.script_scope
+                    .push_constant_dynamic(var_name.as_ref(), rule_fns::to_dynamic(var_value));
             }
         }
-        self.script_scope = scope;
     }
 
-    pub fn compute_stayup_values(&mut self) {
+    /// Re-evaluate rules affected by changed vars, and rules without
+    /// a known value.
+    pub fn compute_stayup_values(&mut self, changed_vars: &HashSet<VarName>) {
+        let mut exceeded_limits = Vec::new();
+        let scope_len = self.script_scope.len();
         for (rule_name, ast) in self.stayup_value_asts.iter() {
+            let affected = self
+                .stayup_deps
+                .get(rule_name)
+                .map(|deps| deps.affected_by(changed_vars))
+                .unwrap_or(true);
+            if !affected && self.stayup_values.contains_key(rule_name) {
+                continue;
+            }
             let result = self
                 .script_engine
                 .eval_ast_with_scope::<bool>(&mut self.script_scope, ast);
-            if let Ok(value) = result {
-                Self::set_stayup_value(&mut self.stayup_values, rule_name.clone(), value);
-            } else {
-                warn!(
-                    "Failed to evaluate stayup rule '{}': '{:?}'",
-                    &rule_name, &result
-                );
-                self.stayup_values.remove(rule_name);
+            // Drop variables defined by the script, so that they don't
+            // leak into other rules or shadow vars.
+            self.script_scope.rewind(scope_len);
+            match result {
+                Ok(value) => {
+                    Self::set_stayup_value(&mut self.stayup_values, rule_name.clone(), value);
+                }
+                Err(e) if is_limit_error(&e) => {
+                    error!(
+                        "Disabling stayup rule '{}', it exceeded script limits: {}",
+                        &rule_name, e
+                    );
+                    exceeded_limits.push(rule_name.clone());
+                }
+                Err(e) => {
+                    warn!("Failed to evaluate stayup rule '{}': '{:?}'", &rule_name, e);
+                    self.stayup_values.remove(rule_name);
+                }
             }
         }
+        for rule_name in exceeded_limits.iter() {
+            self.stayup_value_asts.remove(rule_name);
+            self.stayup_values.remove(rule_name);
+        }
     }
 
     pub fn is_stayup_active(&self) -> bool {
         self.stayup_values.values().any(|is_active| *is_active)
     }
 
+    pub fn active_stayup_rules(&self) -> HashSet<RuleName> {
+        self.stayup_values
+            .iter()
+            .filter(|(_, is_active)| **is_active)
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
     fn set_stayup_value(stayup_values: &mut HashMap<RuleName, bool>, name: RuleName, value: bool) {
         let old_value = stayup_values.get(&name);
         if old_value != Some(&value) {
@@ -87,6 +161,56 @@ impl RuleManager {
         stayup_values.insert(name, value);
     }
 
+    fn restrict_script_engine(engine: &mut RhaiEngine, cfg: &Config) {
+        engine
+            .set_max_operations(cfg.rule_max_operations)
+            .set_max_string_size(cfg.rule_max_string_size)
+            .set_max_array_size(cfg.rule_max_array_size)
+            .set_max_expr_depths(cfg.rule_max_expr_depth)
+            // Scripts can't be allowed to run code built at runtime,
+            // it would bypass compile-time checks of rules.
+            .disable_symbol("eval");
+        engine.on_print(|text| info!("Rule script print: {}", text));
+        engine.on_debug(|text, source, pos| {
+            debug!("Rule script debug {:?} {}: {}", source, pos, text)
+        });
+    }
+
+    /// Check that rules refer only to defined vars of the right data
+    /// types, and record which vars each rule depends on.
+    fn analyze_stayup_value_asts(
+        &mut self,
+        var_defs: &HashMap<VarName, VarDef>,
+    ) -> Result<(), AnyError> {
+        let context = self.script_fns_context.borrow();
+        let category_vars = &context.category_vars;
+        for (rule_name, ast) in self.stayup_value_asts.iter() {
+            let refs = rule_analysis::script_refs(ast);
+            let issues = rule_analysis::check_script_refs(&refs, var_defs, category_vars);
+            if !issues.is_empty() {
+                match self.cfg.rule_check_strictness {
+                    RuleCheckStrictness::Ignore => {}
+                    RuleCheckStrictness::Warn => {
+                        for issue in issues.iter() {
+                            warn!("Stayup rule '{}' {}.", &rule_name, issue);
+                        }
+                    }
+                    RuleCheckStrictness::Fail => {
+                        return Err(anyhow!(
+                            "Stayup rule '{}' failed checks: {}.",
+                            &rule_name,
+                            issues.join(", ")
+                        ));
+                    }
+                }
+            }
+            let deps = refs.rule_deps(category_vars);
+            debug!("Stayup rule '{}' depends on: {:?}", &rule_name, &deps);
+            self.stayup_deps.insert(rule_name.clone(), deps);
+        }
+        Ok(())
+    }
+
     fn compile_stayup_value_asts(&mut self) -> Result<(), AnyError> {
         for (rule_name, rule_def) in self.stayup_defs.iter() {
             trace!("Compiling value script AST for rule '{}'.", &rule_name);
@@ -100,37 +224,178 @@ impl RuleManager {
     }
 }
 
+/// Whether the script evaluation failed on exceeding a configured
+/// limit, possibly inside a function call.
+fn is_limit_error(err: &RhaiEvalAltResult) -> bool {
+    use RhaiEvalAltResult::*;
+    match err {
+        ErrorTooManyOperations(_) | ErrorStackOverflow(_) | ErrorDataTooLarge(_, _) => true,
+        ErrorInFunctionCall(_, _, inner, _) => is_limit_error(inner),
+        _ => false,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::test_helpers::{rule_name, run_and_term_without_builtin_defs_config, var_name};
+    use crate::test_helpers::{
+        default_config, rule_name, run_and_term_without_builtin_defs_config, var_name,
+    };
 
     fn create_rule_manager(cfg: Config) -> RuleManager {
         RuleManager::new(Rc::new(cfg))
     }
 
+    fn update_and_compute(
+        mgr: &mut RuleManager,
+        vars: &HashMap<VarName, VarValue>,
+        changed_vars: &[&str],
+    ) {
+        let changed_vars: HashSet<VarName> = changed_vars.iter().map(|v| var_name(v)).collect();
+        mgr.update_script_scope(vars, &changed_vars, &EvalTime::default());
+        mgr.compute_stayup_values(&changed_vars);
+    }
+
+    fn init_rule_manager(mgr: &mut RuleManager) -> Result<(), AnyError> {
+        let var_defs = files::load_var_defs(&mgr.cfg)?;
+        mgr.init(&var_defs, HashMap::new(), Duration::ZERO)
+    }
+
     #[test]
     fn test_stayup_rules() {
         let mut mgr = create_rule_manager(run_and_term_without_builtin_defs_config());
-        mgr.init().expect("Failed to init RuleManager.");
+        init_rule_manager(&mut mgr).expect("Failed to init RuleManager.");
 
         let mut vars: HashMap<VarName, VarValue> = HashMap::new();
         vars.insert(var_name("test_category"), VarValue::Bool(true));
         vars.insert(var_name("test_poll_true"), VarValue::Bool(true));
 
-        mgr.reset_script_scope(&vars);
-        mgr.compute_stayup_values();
+        update_and_compute(&mut mgr, &vars, &["test_category", "test_poll_true"]);
         assert_eq!(
             mgr.stayup_values.get(&rule_name("test_stayup_bool")),
             Some(&true)
         );
+        assert!(mgr
+            .active_stayup_rules()
+            .contains(&rule_name("test_stayup_bool")));
 
         vars.insert(var_name("test_category"), VarValue::Bool(false));
-        mgr.reset_script_scope(&vars);
-        mgr.compute_stayup_values();
+        update_and_compute(&mut mgr, &vars, &["test_category"]);
         assert_eq!(
             mgr.stayup_values.get(&rule_name("test_stayup_bool")),
             Some(&false)
         );
+        assert!(!mgr
+            .active_stayup_rules()
+            .contains(&rule_name("test_stayup_bool")));
+    }
+
+    #[test]
+    fn test_only_affected_rules_are_evaluated() {
+        let mut mgr = create_rule_manager(run_and_term_without_builtin_defs_config());
+        init_rule_manager(&mut mgr).expect("Failed to init RuleManager.");
+        let test_stayup_bool = rule_name("test_stayup_bool");
+        let mut vars: HashMap<VarName, VarValue> = HashMap::new();
+        vars.insert(var_name("test_category"), VarValue::Bool(true));
+        vars.insert(var_name("test_poll_true"), VarValue::Bool(true));
+        update_and_compute(&mut mgr, &vars, &["test_category", "test_poll_true"]);
+        assert_eq!(mgr.stayup_values.get(&test_stayup_bool), Some(&true));
+
+        // Tamper with the value to see whether the rule gets
+        // re-evaluated.
+        mgr.stayup_values.insert(test_stayup_bool.clone(), false);
+        vars.insert(var_name("test_inactive"), VarValue::Bool(true));
+        update_and_compute(&mut mgr, &vars, &["test_inactive"]);
+        assert_eq!(mgr.stayup_values.get(&test_stayup_bool), Some(&false));
+
+        update_and_compute(&mut mgr, &vars, &["test_poll_true"]);
+        assert_eq!(mgr.stayup_values.get(&test_stayup_bool), Some(&true));
+        // Scope entries got replaced rather than added.
+        assert_eq!(mgr.script_scope.len(), 3);
+    }
+
+    #[test]
+    fn test_script_locals_do_not_leak() {
+        let mut mgr = create_rule_manager(run_and_term_without_builtin_defs_config());
+        init_rule_manager(&mut mgr).expect("Failed to init RuleManager.");
+        let ast = mgr
+            .script_engine
+            .compile("let test_category = false; test_category")
+            .expect("Failed to compile script.");
+        mgr.stayup_value_asts
+            .insert(rule_name("test_shadowing"), ast);
+        let mut vars: HashMap<VarName, VarValue> = HashMap::new();
+        vars.insert(var_name("test_category"), VarValue::Bool(true));
+        vars.insert(var_name("test_poll_true"), VarValue::Bool(true));
+        update_and_compute(&mut mgr, &vars, &["test_category", "test_poll_true"]);
+        assert_eq!(
+            mgr.stayup_values.get(&rule_name("test_stayup_bool")),
+            Some(&true)
+        );
+        assert_eq!(mgr.script_scope.len(), 2);
+    }
+
+    #[test]
+    fn test_rule_exceeding_limits_gets_disabled() {
+        let mut cfg = run_and_term_without_builtin_defs_config();
+        cfg.rule_max_operations = 1_000;
+        let mut mgr = create_rule_manager(cfg);
+        init_rule_manager(&mut mgr).expect("Failed to init RuleManager.");
+        let looping = rule_name("test_looping");
+        let ast = mgr
+            .script_engine
+            .compile("loop {}; true")
+            .expect("Failed to compile script.");
+        mgr.stayup_value_asts.insert(looping.clone(), ast);
+        mgr.stayup_values.insert(looping.clone(), true);
+
+        let mut vars: HashMap<VarName, VarValue> = HashMap::new();
+        vars.insert(var_name("test_category"), VarValue::Bool(true));
+        vars.insert(var_name("test_poll_true"), VarValue::Bool(true));
+        update_and_compute(&mut mgr, &vars, &["test_category", "test_poll_true"]);
+        assert!(!mgr.stayup_value_asts.contains_key(&looping));
+        assert_eq!(mgr.stayup_values.get(&looping), None);
+        // Other rules keep working.
+        assert_eq!(
+            mgr.stayup_values.get(&rule_name("test_stayup_bool")),
+            Some(&true)
+        );
+    }
+
+    #[test]
+    fn test_eval_is_disabled() {
+        let mgr = create_rule_manager(run_and_term_without_builtin_defs_config());
+        assert!(mgr.script_engine.compile("eval(\"true\")").is_err());
+    }
+
+    #[test]
+    fn test_rule_check_strictness() {
+        let mut cfg = run_and_term_without_builtin_defs_config();
+        cfg.rule_check_strictness = RuleCheckStrictness::Fail;
+        let mut mgr = create_rule_manager(cfg);
+        let err = init_rule_manager(&mut mgr).expect_err("Init should have failed.");
+        assert!(err
+            .to_string()
+            .contains("uses undefined var 'test_var_which_does_not_exist'"));
+
+        let mut mgr = create_rule_manager(run_and_term_without_builtin_defs_config());
+        init_rule_manager(&mut mgr).expect("Failed to init RuleManager.");
+        assert_eq!(
+            mgr.stayup_deps.get(&rule_name("test_stayup_bool")),
+            Some(&RuleDeps {
+                vars: [var_name("test_category"), var_name("test_poll_true")].into(),
+                time_dependent: false,
+                dynamic_refs: false,
+            })
+        );
+    }
+
+    #[test]
+    fn test_embedded_rules_pass_checks() {
+        let mut cfg = default_config();
+        cfg.config_dir = "".to_string();
+        cfg.rule_check_strictness = RuleCheckStrictness::Fail;
+        let mut mgr = create_rule_manager(cfg);
+        init_rule_manager(&mut mgr).expect("Embedded rules failed checks.");
     }
 }
@@ -1,18 +1,27 @@
 use crate::config::Config;
 use crate::messages::WorkerMsg;
+use crate::persistent_state::PersistentSleepState;
 use crate::time;
+use crate::wake_reason::{self, WakeReason, WakeupSnapshot};
 use anyhow::Error as AnyError;
-use log::{debug, info};
+use getset::CopyGetters;
+use log::{debug, info, trace};
 use std::rc::Rc;
 use std::time::Duration;
 use tokio::sync::mpsc::UnboundedSender;
 
+#[derive(CopyGetters)]
 pub struct SleepManager {
     cfg: Rc<Config>,
     worker_send: UnboundedSender<WorkerMsg>,
     nearest_possible_suspend: Duration,
     stayup_active: bool,
     suspend_in_progress: bool,
+    sleep_approaching_signalled: bool,
+    scheduled_suspend_tick: Option<Duration>,
+    #[getset(get_copy = "pub")]
+    wake_reason: WakeReason,
+    wakeup_snapshot: WakeupSnapshot,
 }
 
 impl SleepManager {
@@ -23,6 +32,10 @@ impl SleepManager {
             nearest_possible_suspend: Duration::ZERO,
             stayup_active: true,
             suspend_in_progress: false,
+            sleep_approaching_signalled: false,
+            scheduled_suspend_tick: None,
+            wake_reason: WakeReason::None,
+            wakeup_snapshot: WakeupSnapshot::default(),
         }
     }
 
@@ -33,27 +46,118 @@ impl SleepManager {
         Ok(())
     }
 
+    pub fn persistent_state(&self) -> PersistentSleepState {
+        PersistentSleepState {
+            nearest_possible_suspend: self.nearest_possible_suspend.as_millis() as u64,
+            wake_reason: self.wake_reason.to_string(),
+        }
+    }
+
+    /// Restore state saved by a previous waketimed process within the
+    /// same boot. Replaces the startup awake time applied in `init`,
+    /// as the system has already gone through its startup.
+    pub fn restore(&mut self, state: &PersistentSleepState) -> Result<(), AnyError> {
+        self.nearest_possible_suspend = Duration::from_millis(state.nearest_possible_suspend);
+        self.bump_nearest_possible_suspend_from_now(Duration::from_millis(
+            self.cfg.minimum_awake_time,
+        ))?;
+        self.wake_reason = state.wake_reason.parse().unwrap_or(WakeReason::Unknown);
+        Ok(())
+    }
+
     pub fn update(&mut self, stayup_active: bool) -> Result<(), AnyError> {
         self.stayup_active = stayup_active;
         if stayup_active {
             self.bump_nearest_possible_suspend_from_now(Duration::from_millis(
-                self.cfg.stayup_cleared_awake_time + self.cfg.poll_variable_interval,
+                self.cfg.stayup_cleared_awake_time,
             ))?;
         }
         Ok(())
     }
 
-    pub fn suspend_if_allowed(&mut self) -> Result<(), AnyError> {
+    /// Request suspend if it is allowed. Returns whether suspend was
+    /// requested.
+    pub fn suspend_if_allowed(&mut self) -> Result<bool, AnyError> {
         if self.is_suspend_allowed()? && !self.suspend_in_progress {
             self.worker_send
                 .send(WorkerMsg::Suspend(self.cfg.test_mode))?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// Let others know that suspend is near, so that they can e.g.
+    /// save state or take a hold. If a stay-up rule re-activates
+    /// after the signal was sent, a cancellation is sent instead.
+    pub fn signal_sleep_approaching_if_due(&mut self) -> Result<(), AnyError> {
+        let notify_desktop = self.cfg.sleep_approaching_notify_desktop;
+        if self.stayup_active {
+            if self.sleep_approaching_signalled {
+                info!("Sleep is no longer approaching, a stay-up rule is active.");
+                self.sleep_approaching_signalled = false;
+                self.worker_send
+                    .send(WorkerMsg::EmitSleepApproachingCancelled(notify_desktop))?;
+            }
+            return Ok(());
+        }
+        if self.cfg.sleep_approaching_time == 0
+            || self.sleep_approaching_signalled
+            || self.suspend_in_progress
+        {
+            return Ok(());
+        }
+
+        let approaching_time = Duration::from_millis(self.cfg.sleep_approaching_time);
+        let now = time::now()?;
+        if now + approaching_time >= self.nearest_possible_suspend {
+            // Make sure the signal is sent out at least
+            // sleep_approaching_time before the actual suspend.
+            self.bump_nearest_possible_suspend_from_now(approaching_time)?;
+            let until_suspend = self.nearest_possible_suspend - now;
+            info!("Sleep approaching in {} ms.", until_suspend.as_millis());
+            self.sleep_approaching_signalled = true;
+            self.worker_send.send(WorkerMsg::EmitSleepApproaching(
+                until_suspend.as_millis() as u64,
+                notify_desktop,
+            ))?;
         }
         Ok(())
     }
 
+    /// Ask the worker to wake the engine up exactly when the next
+    /// suspend-related action (sleep approaching signal or suspend
+    /// itself) is due, rather than waiting for a var poll tick.
+    pub fn schedule_suspend_tick(&mut self) -> Result<(), AnyError> {
+        let target = match self.next_suspend_tick() {
+            Some(target) => target,
+            None => return Ok(()),
+        };
+        if self.scheduled_suspend_tick == Some(target) {
+            return Ok(());
+        }
+        // Round up, so that the tick does not fire before the target
+        // when nothing is due yet.
+        let from_now_ms = (target.saturating_sub(time::now()?) + Duration::from_nanos(999_999))
+            .as_millis() as u64;
+        trace!("Scheduling suspend tick in {} ms.", from_now_ms);
+        self.scheduled_suspend_tick = Some(target);
+        self.worker_send
+            .send(WorkerMsg::ScheduleSuspendTick(from_now_ms))?;
+        Ok(())
+    }
+
+    /// The scheduled suspend tick has fired, a new one can be
+    /// scheduled even for the same target.
+    pub fn handle_suspend_tick(&mut self) {
+        self.scheduled_suspend_tick = None;
+    }
+
     pub fn handle_system_is_resuming(&mut self) {
         info!("System is resuming.");
         self.suspend_in_progress = false;
+        self.sleep_approaching_signalled = false;
+        self.wake_reason = wake_reason::classify_resume(&self.wakeup_snapshot);
+        info!("Wake reason: {}", self.wake_reason);
         self.bump_nearest_possible_suspend_from_now(Duration::from_millis(
             self.cfg.minimum_awake_time,
         ))
@@ -71,11 +175,27 @@ impl SleepManager {
     pub fn handle_system_is_suspending(&mut self) {
         info!("System is suspending.");
         self.suspend_in_progress = true;
+        self.wakeup_snapshot = wake_reason::take_snapshot();
     }
 
     fn is_suspend_allowed(&self) -> Result<bool, AnyError> {
         let now = time::now()?;
-        Ok(now > self.nearest_possible_suspend && !self.stayup_active)
+        Ok(now >= self.nearest_possible_suspend && !self.stayup_active)
+    }
+
+    fn next_suspend_tick(&self) -> Option<Duration> {
+        if self.stayup_active || self.suspend_in_progress {
+            return None;
+        }
+        let approaching_time = Duration::from_millis(self.cfg.sleep_approaching_time);
+        if approaching_time.is_zero() || self.sleep_approaching_signalled {
+            Some(self.nearest_possible_suspend)
+        } else {
+            Some(
+                self.nearest_possible_suspend
+                    .saturating_sub(approaching_time),
+            )
+        }
     }
 
     fn bump_nearest_possible_suspend_from_now(
@@ -147,7 +267,7 @@ mod tests {
         // allowed.
         mgr.update(false)?;
         assert!(mgr.is_suspend_allowed()?);
-        mgr.suspend_if_allowed()?;
+        assert!(mgr.suspend_if_allowed()?);
         assert_eq!(worker_recv.try_recv(), Ok(WorkerMsg::Suspend(true)));
 
         // Second update now sets stayup_active true again. Suspend
@@ -159,4 +279,91 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_signal_sleep_approaching() -> Result<(), AnyError> {
+        let mut cfg = default_config();
+        cfg.startup_awake_time = 0;
+        cfg.stayup_cleared_awake_time = 1000;
+        cfg.sleep_approaching_time = 500;
+        let (mut mgr, mut worker_recv) = create_sleep_manager(cfg);
+        mgr.init().expect("Failed to init SleepManager.");
+
+        // Stay-up rules active, nothing to signal.
+        mgr.update(true)?;
+        mgr.signal_sleep_approaching_if_due()?;
+        assert_eq!(worker_recv.try_recv(), Err(TryRecvError::Empty));
+
+        // Stay-up rules cleared, but suspend is still further away
+        // than sleep_approaching_time.
+        mgr.update(false)?;
+        mgr.signal_sleep_approaching_if_due()?;
+        assert_eq!(worker_recv.try_recv(), Err(TryRecvError::Empty));
+
+        // Once suspend gets near, the signal is sent out only once,
+        // and suspend is postponed so that it does not happen sooner
+        // than sleep_approaching_time after the signal.
+        mgr.nearest_possible_suspend = time::now()?;
+        mgr.signal_sleep_approaching_if_due()?;
+        assert!(matches!(
+            worker_recv.try_recv(),
+            Ok(WorkerMsg::EmitSleepApproaching(_, false))
+        ));
+        assert!(!mgr.is_suspend_allowed()?);
+        mgr.signal_sleep_approaching_if_due()?;
+        assert_eq!(worker_recv.try_recv(), Err(TryRecvError::Empty));
+
+        // Stay-up rule re-activated, the signal gets cancelled.
+        mgr.update(true)?;
+        mgr.signal_sleep_approaching_if_due()?;
+        assert_eq!(
+            worker_recv.try_recv(),
+            Ok(WorkerMsg::EmitSleepApproachingCancelled(false))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_schedule_suspend_tick() -> Result<(), AnyError> {
+        let mut cfg = default_config();
+        cfg.startup_awake_time = 0;
+        cfg.stayup_cleared_awake_time = 10_000;
+        cfg.sleep_approaching_time = 4_000;
+        let (mut mgr, mut worker_recv) = create_sleep_manager(cfg);
+        mgr.init().expect("Failed to init SleepManager.");
+
+        // No tick while a stay-up rule is active.
+        mgr.update(true)?;
+        mgr.schedule_suspend_tick()?;
+        assert_eq!(worker_recv.try_recv(), Err(TryRecvError::Empty));
+
+        // Once stay-up rules clear, the tick is scheduled for the
+        // sleep approaching signal, and only once.
+        mgr.update(false)?;
+        mgr.schedule_suspend_tick()?;
+        match worker_recv.try_recv() {
+            Ok(WorkerMsg::ScheduleSuspendTick(ms)) => assert!(ms > 5_000 && ms <= 6_000),
+            other => panic!("Unexpected message: {other:?}"),
+        }
+        mgr.schedule_suspend_tick()?;
+        assert_eq!(worker_recv.try_recv(), Err(TryRecvError::Empty));
+
+        // After the signal, the tick is scheduled for the suspend.
+        mgr.sleep_approaching_signalled = true;
+        mgr.schedule_suspend_tick()?;
+        match worker_recv.try_recv() {
+            Ok(WorkerMsg::ScheduleSuspendTick(ms)) => assert!(ms > 9_000 && ms <= 10_000),
+            other => panic!("Unexpected message: {other:?}"),
+        }
+
+        // A tick which fired before anything was due gets scheduled
+        // again for the same target.
+        mgr.handle_suspend_tick();
+        mgr.schedule_suspend_tick()?;
+        match worker_recv.try_recv() {
+            Ok(WorkerMsg::ScheduleSuspendTick(ms)) => assert!(ms > 9_000 && ms <= 10_000),
+            other => panic!("Unexpected message: {other:?}"),
+        }
+        Ok(())
+    }
 }
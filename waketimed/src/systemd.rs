@@ -0,0 +1,132 @@
+use anyhow::{anyhow, Error as AnyError};
+use zbus::Connection as ZbusConnection;
+
+const SYSTEMD_DEST: &str = "org.freedesktop.systemd1";
+const SYSTEMD_PATH: &str = "/org/freedesktop/systemd1";
+const SYSTEMD_MANAGER_INTERFACE: &str = "org.freedesktop.systemd1.Manager";
+const SYSTEMD_UNIT_INTERFACE: &str = "org.freedesktop.systemd1.Unit";
+
+/// Get `ActiveState` of a systemd unit. Units which aren't loaded are
+/// reported as "inactive".
+pub async fn unit_active_state(
+    system_dbus_conn: &ZbusConnection,
+    unit: &str,
+) -> Result<String, AnyError> {
+    let get_unit_res = system_dbus_conn
+        .call_method(
+            Some(SYSTEMD_DEST),
+            SYSTEMD_PATH,
+            Some(SYSTEMD_MANAGER_INTERFACE),
+            "GetUnit",
+            &(unit,),
+        )
+        .await;
+    let unit_msg = match get_unit_res {
+        Ok(msg) => msg,
+        Err(zbus::Error::MethodError(name, _, _))
+            if name.as_str() == "org.freedesktop.systemd1.NoSuchUnit" =>
+        {
+            return Ok("inactive".to_string());
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let unit_path: zvariant::OwnedObjectPath = unit_msg.body()?;
+    let state_msg = system_dbus_conn
+        .call_method(
+            Some(SYSTEMD_DEST),
+            unit_path.as_str(),
+            Some("org.freedesktop.DBus.Properties"),
+            "Get",
+            &[SYSTEMD_UNIT_INTERFACE, "ActiveState"],
+        )
+        .await?;
+    let state: zvariant::Value = state_msg.body()?;
+    if let zvariant::Value::Str(state) = state {
+        Ok(state.to_string())
+    } else {
+        Err(anyhow!("Wrong data type of unit ActiveState."))
+    }
+}
+
+/// List loaded units matching any of the glob `patterns`, with their
+/// `ActiveState`.
+pub async fn unit_active_states_by_patterns(
+    system_dbus_conn: &ZbusConnection,
+    patterns: &[String],
+) -> Result<Vec<(String, String)>, AnyError> {
+    let units_msg = system_dbus_conn
+        .call_method(
+            Some(SYSTEMD_DEST),
+            SYSTEMD_PATH,
+            Some(SYSTEMD_MANAGER_INTERFACE),
+            "ListUnitsByPatterns",
+            &(Vec::<String>::new(), patterns),
+        )
+        .await?;
+    // (name, description, load state, active state, sub state,
+    // followed unit, unit path, job id, job type, job path)
+    #[allow(clippy::type_complexity)]
+    let units: Vec<(
+        String,
+        String,
+        String,
+        String,
+        String,
+        String,
+        zvariant::OwnedObjectPath,
+        u32,
+        String,
+        zvariant::OwnedObjectPath,
+    )> = units_msg.body()?;
+    Ok(units
+        .into_iter()
+        .map(|(name, _, _, active_state, ..)| (name, active_state))
+        .collect())
+}
+
+/// List units which have a job queued in the systemd job queue.
+pub async fn queued_job_units(system_dbus_conn: &ZbusConnection) -> Result<Vec<String>, AnyError> {
+    let jobs_msg = system_dbus_conn
+        .call_method(
+            Some(SYSTEMD_DEST),
+            SYSTEMD_PATH,
+            Some(SYSTEMD_MANAGER_INTERFACE),
+            "ListJobs",
+            &(),
+        )
+        .await?;
+    // (job id, unit name, job type, job state, job path, unit path)
+    let jobs: Vec<(
+        u32,
+        String,
+        String,
+        String,
+        zvariant::OwnedObjectPath,
+        zvariant::OwnedObjectPath,
+    )> = jobs_msg.body()?;
+    Ok(jobs.into_iter().map(|(_, unit, ..)| unit).collect())
+}
+
+/// Whether a unit in the given `ActiveState` is doing something,
+/// i.e. it is running or changing its state.
+pub fn is_active_state_busy(active_state: &str) -> bool {
+    matches!(
+        active_state,
+        "active" | "activating" | "deactivating" | "reloading"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_active_state_busy() {
+        assert!(is_active_state_busy("active"));
+        assert!(is_active_state_busy("activating"));
+        assert!(is_active_state_busy("deactivating"));
+        assert!(is_active_state_busy("reloading"));
+        assert!(!is_active_state_busy("inactive"));
+        assert!(!is_active_state_busy("failed"));
+    }
+}
@@ -1,6 +1,11 @@
 use crate::config::Config;
 use crate::core::rules::RuleName;
 use crate::core::vars::VarName;
+use anyhow::Error as AnyError;
+use std::fs;
+use std::future::Future;
+use std::path::PathBuf;
+use zbus::{Connection as ZbusConnection, ConnectionBuilder as ZbusConnectionBuilder};
 
 pub fn default_config() -> Config {
     let mut cfg: Config = serde_yaml::from_str("{}").expect("Unable to create default Config.");
@@ -29,3 +34,53 @@ pub fn rule_name(name: &str) -> RuleName {
 pub fn var_name(name: &str) -> VarName {
     VarName::try_from(name.to_string()).expect("Invalid VarName")
 }
+
+/// Create an empty directory for a test to write into.
+pub fn temp_dir(test_name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "waketimed-test-{}-{}",
+        test_name,
+        std::process::id()
+    ));
+    if dir.exists() {
+        fs::remove_dir_all(&dir).expect("Failed to remove old test directory.");
+    }
+    fs::create_dir_all(&dir).expect("Failed to create test directory.");
+    dir
+}
+
+/// Run a future to completion on a fresh single-threaded runtime.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("Failed to build tokio runtime.")
+        .block_on(future)
+}
+
+/// Create a pair of peer-to-peer D-Bus connections for mocking a
+/// service. The first connection serves the `mock` object at `path`,
+/// pass the second to the code under test instead of a system bus
+/// connection. Keep both connections alive for the test duration.
+pub async fn mock_bus_connections<I: zbus::Interface>(
+    path: &str,
+    mock: I,
+) -> Result<(ZbusConnection, ZbusConnection), AnyError> {
+    let (service_stream, client_stream) = tokio::net::UnixStream::pair()?;
+    let guid = zbus::Guid::generate();
+    // The mock is registered at build time, otherwise a method call
+    // could arrive before the object server listens. Both sides need
+    // to be built concurrently to finish the handshake.
+    let (service_conn, client_conn) = futures_util::future::try_join(
+        ZbusConnectionBuilder::unix_stream(service_stream)
+            .server(&guid)
+            .p2p()
+            .serve_at(path, mock)?
+            .build(),
+        ZbusConnectionBuilder::unix_stream(client_stream)
+            .p2p()
+            .build(),
+    )
+    .await?;
+    Ok((service_conn, client_conn))
+}
@@ -1,6 +1,7 @@
 use anyhow::Error as AnyError;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Local, Utc};
 use nix::time::{clock_gettime, ClockId};
+use std::env;
 use std::time::Duration;
 
 const SUSPEND_CLOCK: ClockId = ClockId::CLOCK_BOOTTIME_ALARM;
@@ -9,8 +10,28 @@ pub fn now() -> Result<Duration, AnyError> {
     Ok(clock_gettime(SUSPEND_CLOCK)?.into())
 }
 
+/// Current CLOCK_MONOTONIC time, which e.g. login manager uses for
+/// idle hints.
+pub fn monotonic_now() -> Result<Duration, AnyError> {
+    Ok(clock_gettime(ClockId::CLOCK_MONOTONIC)?.into())
+}
+
 pub fn from_suspend_to_utc(suspend_clock_time: Duration) -> Result<DateTime<Utc>, AnyError> {
     let time_now = now()?;
     let from_now = chrono::Duration::from_std(suspend_clock_time - time_now)?;
     Ok(Utc::now() + from_now)
 }
+
+/// Current wall-clock time in the local time zone.
+pub fn local_now() -> DateTime<Local> {
+    Local::now()
+}
+
+/// Override the system time zone for local time. Must be called
+/// before spawning any threads, as it modifies the process
+/// environment. Empty string keeps the system time zone.
+pub fn set_local_time_zone(time_zone: &str) {
+    if !time_zone.is_empty() {
+        env::set_var("TZ", time_zone);
+    }
+}
@@ -0,0 +1,133 @@
+use anyhow::{anyhow, Context, Error as AnyError};
+use log::{debug, trace, warn};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use zbus::{Connection as ZbusConnection, ConnectionBuilder as ZbusConnectionBuilder};
+
+/// Find UIDs of users who have an active login session, according to
+/// the login manager.
+pub async fn active_session_uids(system_dbus_conn: &ZbusConnection) -> Result<Vec<u32>, AnyError> {
+    let list_sessions_msg = system_dbus_conn
+        .call_method(
+            Some("org.freedesktop.login1"),
+            "/org/freedesktop/login1",
+            Some("org.freedesktop.login1.Manager"),
+            "ListSessions",
+            &(),
+        )
+        .await
+        .context("Failed to list login sessions")?;
+    // (session id, uid, user name, seat id, session object path)
+    let sessions: Vec<(String, u32, String, String, zvariant::OwnedObjectPath)> =
+        list_sessions_msg.body()?;
+
+    let mut uids = BTreeSet::new();
+    for (session_id, uid, _, _, session_path) in sessions.iter() {
+        match is_session_active(system_dbus_conn, session_path.as_str()).await {
+            Ok(true) => {
+                uids.insert(*uid);
+            }
+            Ok(false) => {}
+            Err(e) => warn!(
+                "Failed to check whether session '{}' is active: {:#}",
+                session_id, e
+            ),
+        }
+    }
+    Ok(uids.into_iter().collect())
+}
+
+/// Connect to the session bus of the given user.
+pub async fn connect_user_bus(uid: u32) -> Result<ZbusConnection, AnyError> {
+    let bus_path = format!("/run/user/{uid}/bus");
+    if !Path::new(&bus_path).exists() {
+        return Err(anyhow!("User bus socket '{}' does not exist.", bus_path));
+    }
+    trace!("Connecting to user bus '{}'.", &bus_path);
+    let conn = ZbusConnectionBuilder::address(format!("unix:path={bus_path}").as_str())?
+        .build()
+        .await
+        .with_context(|| format!("Failed to connect to user bus '{bus_path}'"))?;
+    Ok(conn)
+}
+
+/// Connections to session buses of users with an active login
+/// session. Connections are cached and shared by clones, so that
+/// callers polling user buses don't reconnect every time.
+#[derive(Clone, Debug)]
+pub struct UserBuses {
+    system_dbus_conn: ZbusConnection,
+    conns: Arc<Mutex<HashMap<u32, ZbusConnection>>>,
+    // Users whose bus couldn't be reached, to warn only once until
+    // the bus connects.
+    unreachable_uids: Arc<Mutex<HashSet<u32>>>,
+}
+
+impl UserBuses {
+    pub fn new(system_dbus_conn: ZbusConnection) -> Self {
+        Self {
+            system_dbus_conn,
+            conns: Arc::new(Mutex::new(HashMap::new())),
+            unreachable_uids: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    /// Get connections to session buses of all users with an active
+    /// login session. Users whose bus can't be reached are skipped,
+    /// connections of users who are no longer active are dropped.
+    pub async fn active_connections(&self) -> Result<Vec<(u32, ZbusConnection)>, AnyError> {
+        let uids = active_session_uids(&self.system_dbus_conn).await?;
+        let mut conns = self.conns.lock().await;
+        let mut unreachable_uids = self.unreachable_uids.lock().await;
+        conns.retain(|uid, _| uids.contains(uid));
+        unreachable_uids.retain(|uid| uids.contains(uid));
+        for uid in uids.into_iter() {
+            if conns.contains_key(&uid) {
+                continue;
+            }
+            match connect_user_bus(uid).await {
+                Ok(conn) => {
+                    conns.insert(uid, conn);
+                    unreachable_uids.remove(&uid);
+                }
+                Err(e) if unreachable_uids.insert(uid) => {
+                    warn!("Skipping session bus of user {}: {:#}", uid, e)
+                }
+                Err(e) => debug!("Skipping session bus of user {}: {:#}", uid, e),
+            }
+        }
+        Ok(conns
+            .iter()
+            .map(|(uid, conn)| (*uid, conn.clone()))
+            .collect())
+    }
+
+    /// Drop the cached connection of a user, e.g. after a call on it
+    /// failed. The next `active_connections` call reconnects.
+    pub async fn forget(&self, uid: u32) {
+        self.conns.lock().await.remove(&uid);
+    }
+}
+
+async fn is_session_active(
+    system_dbus_conn: &ZbusConnection,
+    session_path: &str,
+) -> Result<bool, AnyError> {
+    let active_msg = system_dbus_conn
+        .call_method(
+            Some("org.freedesktop.login1"),
+            session_path,
+            Some("org.freedesktop.DBus.Properties"),
+            "Get",
+            &["org.freedesktop.login1.Session", "Active"],
+        )
+        .await?;
+    let body_value: zvariant::Value = active_msg.body()?;
+    if let zvariant::Value::Bool(active) = body_value {
+        Ok(active)
+    } else {
+        Err(anyhow!("Wrong data type."))
+    }
+}
@@ -1,13 +1,18 @@
+use crate::user_buses::UserBuses;
 use anyhow::{anyhow, Error as AnyError};
 use zbus::Connection as ZbusConnection;
 
 pub struct VarCreationContext {
     pub system_dbus_conn: Option<ZbusConnection>,
+    pub user_buses: Option<UserBuses>,
 }
 
 impl VarCreationContext {
-    pub fn new(system_dbus_conn: Option<ZbusConnection>) -> Self {
-        Self { system_dbus_conn }
+    pub fn new(system_dbus_conn: Option<ZbusConnection>, user_buses: Option<UserBuses>) -> Self {
+        Self {
+            system_dbus_conn,
+            user_buses,
+        }
     }
 
     pub fn system_dbus_conn(&self) -> Result<ZbusConnection, AnyError> {
@@ -15,4 +20,10 @@ impl VarCreationContext {
             anyhow!("VarCreationContext does not contain a connection to system D-Bus.")
         })
     }
+
+    pub fn user_buses(&self) -> Result<UserBuses, AnyError> {
+        self.user_buses.clone().ok_or_else(|| {
+            anyhow!("VarCreationContext does not contain user session bus connections.")
+        })
+    }
 }
@@ -1,5 +1,9 @@
 use crate::core::vars::{BuiltinPollDef, VarDef, VarKind, VarValue};
 use crate::var_creation_context::VarCreationContext;
+use crate::var_fns::poll::bluetooth::BluetoothVar;
+use crate::var_fns::poll::login_seat_busy::LoginSeatVar;
+use crate::var_fns::poll::network_manager::NetworkManagerVar;
+use crate::var_fns::poll::power_supply::PowerSupplyVar;
 use anyhow::{anyhow, Error as AnyError};
 use async_trait::async_trait;
 
@@ -35,15 +39,109 @@ fn new_builtin_poll_var_fns(
 ) -> Result<Box<dyn PollVarFns>, AnyError> {
     match bp_def.builtin_name.as_str() {
         "login_seat_busy" => Ok(Box::new(poll::login_seat_busy::LoginSeatBusyFns::new(
+            LoginSeatVar::Busy,
+            &bp_def.params,
+            context,
+        )?)),
+        "login_seat_idle_ms" => Ok(Box::new(poll::login_seat_busy::LoginSeatBusyFns::new(
+            LoginSeatVar::IdleMs,
+            &bp_def.params,
+            context,
+        )?)),
+        "audio_playback_active" => Ok(Box::new(
+            poll::audio_playback_active::AudioPlaybackActiveFns::new(&bp_def.params)?,
+        )),
+        "bluetooth_audio_active" => Ok(Box::new(poll::bluetooth::BluetoothFns::new(
+            BluetoothVar::AudioTransportActive,
+            &bp_def.params,
+            context,
+        )?)),
+        "bluetooth_connected" => Ok(Box::new(poll::bluetooth::BluetoothFns::new(
+            BluetoothVar::DeviceConnected,
+            &bp_def.params,
+            context,
+        )?)),
+        "backlight_on" => Ok(Box::new(poll::display::BacklightOnFns::new(
+            &bp_def.params,
+        )?)),
+        "battery_percent" => Ok(Box::new(poll::power_supply::PowerSupplyFns::new(
+            PowerSupplyVar::BatteryPercent,
+            &bp_def.params,
+            context,
+        )?)),
+        "charging" => Ok(Box::new(poll::power_supply::PowerSupplyFns::new(
+            PowerSupplyVar::Charging,
+            &bp_def.params,
+            context,
+        )?)),
+        "cpu_utilization" => Ok(Box::new(poll::system_load::CpuUtilizationFns::new(
+            &bp_def.params,
+        )?)),
+        "display_on" => Ok(Box::new(poll::display::DisplayOnFns::new(&bp_def.params)?)),
+        "lid_closed" => Ok(Box::new(poll::lid_closed::LidClosedFns::new(
+            &bp_def.params,
+            context,
+        )?)),
+        "load_average" => Ok(Box::new(poll::system_load::LoadAverageFns::new(
+            &bp_def.params,
+        )?)),
+        "network_busy" => Ok(Box::new(poll::network_busy::NetworkBusyFns::new(
+            &bp_def.params,
+        )?)),
+        "network_connection_type" => Ok(Box::new(poll::network_manager::NetworkManagerFns::new(
+            NetworkManagerVar::ConnectionType,
+            &bp_def.params,
+            context,
+        )?)),
+        "network_connectivity" => Ok(Box::new(poll::network_manager::NetworkManagerFns::new(
+            NetworkManagerVar::Connectivity,
+            &bp_def.params,
+            context,
+        )?)),
+        "network_metered" => Ok(Box::new(poll::network_manager::NetworkManagerFns::new(
+            NetworkManagerVar::Metered,
+            &bp_def.params,
+            context,
+        )?)),
+        "on_ac_power" => Ok(Box::new(poll::power_supply::PowerSupplyFns::new(
+            PowerSupplyVar::OnAcPower,
+            &bp_def.params,
+            context,
+        )?)),
+        "process_running" => Ok(Box::new(poll::process_running::ProcessRunningFns::new(
+            &bp_def.params,
+            context,
+        )?)),
+        "remote_session" => Ok(Box::new(poll::remote_session::RemoteSessionFns::new(
             &bp_def.params,
             context,
         )?)),
         "sleep_block_inhibited" => Ok(Box::new(
             poll::sleep_block_inhibited::SleepBlockInhibitedFns::new(&bp_def.params, context)?,
         )),
+        "media_playing" => Ok(Box::new(poll::media_playing::MediaPlayingFns::new(
+            &bp_def.params,
+            context,
+        )?)),
+        "modem_sms_received" => Ok(Box::new(
+            poll::modem_sms_received::ModemSmsReceivedFns::new(&bp_def.params, context)?,
+        )),
         "modem_voice_call_present" => Ok(Box::new(
             poll::modem_voice_call_present::ModemVoiceCallPresentFns::new(&bp_def.params, context)?,
         )),
+        "systemd_activity" => Ok(Box::new(poll::systemd_activity::SystemdActivityFns::new(
+            &bp_def.params,
+            context,
+        )?)),
+        "thermal_max_celsius" => Ok(Box::new(poll::thermal::ThermalMaxCelsiusFns::new(
+            &bp_def.params,
+        )?)),
+        "wake_lock_held" => Ok(Box::new(poll::wakelocks::WakeLockHeldFns::new(
+            &bp_def.params,
+        )?)),
+        "wakeup_source_active" => Ok(Box::new(poll::wakelocks::WakeupSourceActiveFns::new(
+            &bp_def.params,
+        )?)),
 
         "test_poll_bool" => Ok(Box::new(poll::test_poll_bool::TestPollBoolFns::new(
             &bp_def.params,
@@ -0,0 +1,120 @@
+use crate::core::vars::VarValue;
+use crate::var_fns::PollVarFns;
+use anyhow::Error as AnyError;
+use async_trait::async_trait;
+use log::{debug, trace};
+use serde_yaml::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const ASOUND_DIR: &str = "/proc/asound";
+
+#[derive(Clone, Debug)]
+pub struct AudioPlaybackActiveFns {
+    asound_dir: PathBuf,
+}
+
+impl AudioPlaybackActiveFns {
+    pub fn new(_params: &HashMap<String, Value>) -> Result<Self, AnyError> {
+        Ok(Self {
+            asound_dir: PathBuf::from(ASOUND_DIR),
+        })
+    }
+}
+
+#[async_trait]
+impl PollVarFns for AudioPlaybackActiveFns {
+    async fn poll(&mut self) -> Option<VarValue> {
+        Some(VarValue::Bool(any_playback_running(&self.asound_dir)))
+    }
+}
+
+/// Check whether any ALSA playback substream is running, i.e. some
+/// audio is being sent to a sink. Sound servers keep the substream
+/// open while idle, but only run it while something plays.
+fn any_playback_running(asound_dir: &Path) -> bool {
+    for card_dir in subdirs_with_prefix(asound_dir, "card") {
+        // Playback PCM devices are named e.g. pcm0p, capture ones
+        // pcm0c.
+        for pcm_dir in subdirs_with_prefix(&card_dir, "pcm") {
+            let is_playback = pcm_dir
+                .file_name()
+                .map(|name| name.to_string_lossy().ends_with('p'))
+                .unwrap_or(false);
+            if !is_playback {
+                continue;
+            }
+            for sub_dir in subdirs_with_prefix(&pcm_dir, "sub") {
+                let status = fs::read_to_string(sub_dir.join("status")).unwrap_or_default();
+                if is_status_running(&status) {
+                    trace!("Audio playback running in '{}'.", sub_dir.display());
+                    return true;
+                }
+            }
+        }
+    }
+    false
+}
+
+fn is_status_running(status: &str) -> bool {
+    status.lines().any(|line| {
+        let mut parts = line.splitn(2, ':');
+        let key = parts.next().unwrap_or_default().trim();
+        let value = parts.next().unwrap_or_default().trim();
+        key == "state" && (value == "RUNNING" || value == "DRAINING")
+    })
+}
+
+fn subdirs_with_prefix(dir: &Path, prefix: &str) -> Vec<PathBuf> {
+    match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_name().to_string_lossy().starts_with(prefix))
+            .map(|e| e.path())
+            .filter(|path| path.is_dir())
+            .collect(),
+        Err(e) => {
+            debug!("Failed to read '{}': {}", dir.display(), e);
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::temp_dir;
+
+    fn write_status(asound_dir: &Path, sub_path: &str, status: &str) {
+        let sub_dir = asound_dir.join(sub_path);
+        fs::create_dir_all(&sub_dir).expect("Failed to create substream dir.");
+        fs::write(sub_dir.join("status"), status).expect("Failed to write status.");
+    }
+
+    #[test]
+    fn test_any_playback_running() {
+        let dir = temp_dir("audio_playback_active");
+        assert!(!any_playback_running(&dir));
+
+        write_status(&dir, "card0/pcm0p/sub0", "closed\n");
+        write_status(
+            &dir,
+            "card0/pcm0c/sub0",
+            "state: RUNNING\nowner_pid   : 1234\n",
+        );
+        write_status(
+            &dir,
+            "card1/pcm3p/sub0",
+            "state: PREPARED\nowner_pid   : 1234\n",
+        );
+        assert!(!any_playback_running(&dir));
+
+        write_status(
+            &dir,
+            "card1/pcm3p/sub1",
+            "state: RUNNING\nowner_pid   : 1234\ntrigger_time: 1.5\n",
+        );
+        assert!(any_playback_running(&dir));
+    }
+}
@@ -0,0 +1,196 @@
+use crate::core::vars::VarValue;
+use crate::var_creation_context::VarCreationContext;
+use crate::var_fns::PollVarFns;
+use anyhow::{anyhow, Error as AnyError};
+use async_trait::async_trait;
+use log::{trace, warn};
+use serde_yaml::Value;
+use std::collections::HashMap;
+use zbus::Connection as ZbusConnection;
+
+const BLUEZ_DEST: &str = "org.bluez";
+const BLUEZ_DEVICE_INTERFACE: &str = "org.bluez.Device1";
+const BLUEZ_TRANSPORT_INTERFACE: &str = "org.bluez.MediaTransport1";
+// MediaTransport1 State value while audio is streamed. Other states
+// are "idle" and "pending".
+const TRANSPORT_STATE_ACTIVE: &str = "active";
+
+/// Object path -> interface name -> property name -> value, as
+/// returned by `org.freedesktop.DBus.ObjectManager.GetManagedObjects`.
+type ManagedObjects =
+    HashMap<zvariant::OwnedObjectPath, HashMap<String, HashMap<String, zvariant::OwnedValue>>>;
+
+/// Which Bluetooth state the poll var reports.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BluetoothVar {
+    DeviceConnected,
+    AudioTransportActive,
+}
+
+#[derive(Clone, Debug)]
+pub struct BluetoothFns {
+    var: BluetoothVar,
+    system_dbus_conn: ZbusConnection,
+}
+
+impl BluetoothFns {
+    pub fn new(
+        var: BluetoothVar,
+        _params: &HashMap<String, Value>,
+        context: &VarCreationContext,
+    ) -> Result<Self, AnyError> {
+        Ok(Self {
+            var,
+            system_dbus_conn: context.system_dbus_conn()?,
+        })
+    }
+}
+
+#[async_trait]
+impl PollVarFns for BluetoothFns {
+    async fn poll(&mut self) -> Option<VarValue> {
+        let objects = fetch_bluez_objects(&self.system_dbus_conn)
+            .await
+            .map_err(|e| warn!("Failed to fetch BlueZ objects: {:#}", e))
+            .ok()?;
+        let value = match self.var {
+            BluetoothVar::DeviceConnected => any_device_connected(&objects),
+            BluetoothVar::AudioTransportActive => any_transport_active(&objects),
+        };
+        Some(VarValue::Bool(value))
+    }
+}
+
+async fn fetch_bluez_objects(
+    system_dbus_conn: &ZbusConnection,
+) -> Result<ManagedObjects, AnyError> {
+    let objects_res = system_dbus_conn
+        .call_method(
+            Some(BLUEZ_DEST),
+            "/",
+            Some("org.freedesktop.DBus.ObjectManager"),
+            "GetManagedObjects",
+            &(),
+        )
+        .await;
+    match objects_res {
+        Ok(msg) => Ok(msg.body()?),
+        // Without BlueZ running there are no Bluetooth devices.
+        Err(zbus::Error::MethodError(name, _, _))
+            if name.as_str() == "org.freedesktop.DBus.Error.ServiceUnknown" =>
+        {
+            trace!("BlueZ is not running.");
+            Ok(ManagedObjects::new())
+        }
+        Err(e) => Err(anyhow!(e)),
+    }
+}
+
+fn any_device_connected(objects: &ManagedObjects) -> bool {
+    objects_with_property(objects, BLUEZ_DEVICE_INTERFACE, "Connected").any(|(path, value)| {
+        let connected = matches!(&**value, zvariant::Value::Bool(true));
+        if connected {
+            trace!("Bluetooth device '{}' is connected.", path.as_str());
+        }
+        connected
+    })
+}
+
+fn any_transport_active(objects: &ManagedObjects) -> bool {
+    objects_with_property(objects, BLUEZ_TRANSPORT_INTERFACE, "State").any(|(path, value)| {
+        let active = matches!(&**value, zvariant::Value::Str(state) if state.as_str() == TRANSPORT_STATE_ACTIVE);
+        if active {
+            trace!("Bluetooth media transport '{}' is active.", path.as_str());
+        }
+        active
+    })
+}
+
+fn objects_with_property<'a>(
+    objects: &'a ManagedObjects,
+    interface: &'a str,
+    property: &'a str,
+) -> impl Iterator<Item = (&'a zvariant::OwnedObjectPath, &'a zvariant::OwnedValue)> {
+    objects.iter().filter_map(move |(path, interfaces)| {
+        interfaces
+            .get(interface)
+            .and_then(|props| props.get(property))
+            .map(|value| (path, value))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{block_on, mock_bus_connections};
+
+    struct MockBluez {
+        objects: ManagedObjects,
+    }
+
+    #[zbus::dbus_interface(name = "org.freedesktop.DBus.ObjectManager")]
+    impl MockBluez {
+        fn get_managed_objects(&self) -> ManagedObjects {
+            self.objects.clone()
+        }
+    }
+
+    fn object(
+        path: &str,
+        interface: &str,
+        props: &[(&str, zvariant::Value)],
+    ) -> (
+        zvariant::OwnedObjectPath,
+        HashMap<String, HashMap<String, zvariant::OwnedValue>>,
+    ) {
+        let props = props
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.clone().into()))
+            .collect();
+        (
+            zvariant::ObjectPath::try_from(path).unwrap().into(),
+            HashMap::from([(interface.to_string(), props)]),
+        )
+    }
+
+    async fn fetch_from_mock(objects: ManagedObjects) -> Result<ManagedObjects, AnyError> {
+        let (_service_conn, client_conn) = mock_bus_connections("/", MockBluez { objects }).await?;
+        fetch_bluez_objects(&client_conn).await
+    }
+
+    #[test]
+    fn test_bluez_vars() -> Result<(), AnyError> {
+        let idle = ManagedObjects::from([
+            object(
+                "/org/bluez/hci0",
+                "org.bluez.Adapter1",
+                &[("Powered", true.into())],
+            ),
+            object(
+                "/org/bluez/hci0/dev_00_11_22_33_44_55",
+                BLUEZ_DEVICE_INTERFACE,
+                &[("Connected", false.into())],
+            ),
+        ]);
+        let objects = block_on(fetch_from_mock(idle))?;
+        assert!(!any_device_connected(&objects));
+        assert!(!any_transport_active(&objects));
+
+        let streaming = ManagedObjects::from([
+            object(
+                "/org/bluez/hci0/dev_00_11_22_33_44_55",
+                BLUEZ_DEVICE_INTERFACE,
+                &[("Connected", true.into())],
+            ),
+            object(
+                "/org/bluez/hci0/dev_00_11_22_33_44_55/sep1/fd0",
+                BLUEZ_TRANSPORT_INTERFACE,
+                &[("State", "active".into())],
+            ),
+        ]);
+        let objects = block_on(fetch_from_mock(streaming))?;
+        assert!(any_device_connected(&objects));
+        assert!(any_transport_active(&objects));
+        Ok(())
+    }
+}
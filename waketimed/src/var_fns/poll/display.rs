@@ -0,0 +1,169 @@
+use crate::core::vars::VarValue;
+use crate::var_fns::PollVarFns;
+use anyhow::Error as AnyError;
+use async_trait::async_trait;
+use log::{debug, trace};
+use serde_yaml::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const BACKLIGHT_CLASS_DIR: &str = "/sys/class/backlight";
+const DRM_CLASS_DIR: &str = "/sys/class/drm";
+// Value of bl_power when the backlight is powered on
+// (FB_BLANK_UNBLANK).
+const BL_POWER_UNBLANK: &str = "0";
+
+/// Reports whether any backlight is powered on with non-zero
+/// brightness. Undefined when the device has no backlight.
+#[derive(Clone, Debug)]
+pub struct BacklightOnFns {
+    class_dir: PathBuf,
+}
+
+impl BacklightOnFns {
+    pub fn new(_params: &HashMap<String, Value>) -> Result<Self, AnyError> {
+        Ok(Self {
+            class_dir: PathBuf::from(BACKLIGHT_CLASS_DIR),
+        })
+    }
+}
+
+#[async_trait]
+impl PollVarFns for BacklightOnFns {
+    async fn poll(&mut self) -> Option<VarValue> {
+        any_backlight_on(&self.class_dir).map(VarValue::Bool)
+    }
+}
+
+/// Reports whether any connected DRM connector (display output) has
+/// its DPMS state on. Undefined when no display is connected.
+#[derive(Clone, Debug)]
+pub struct DisplayOnFns {
+    class_dir: PathBuf,
+}
+
+impl DisplayOnFns {
+    pub fn new(_params: &HashMap<String, Value>) -> Result<Self, AnyError> {
+        Ok(Self {
+            class_dir: PathBuf::from(DRM_CLASS_DIR),
+        })
+    }
+}
+
+#[async_trait]
+impl PollVarFns for DisplayOnFns {
+    async fn poll(&mut self) -> Option<VarValue> {
+        any_connector_on(&self.class_dir).map(VarValue::Bool)
+    }
+}
+
+fn any_backlight_on(class_dir: &Path) -> Option<bool> {
+    let backlight_dirs = subdirs(class_dir);
+    if backlight_dirs.is_empty() {
+        return None;
+    }
+    Some(backlight_dirs.iter().any(|dir| {
+        // Drivers which don't support bl_power only use brightness.
+        let powered = read_trimmed(dir, "bl_power")
+            .map(|bl_power| bl_power == BL_POWER_UNBLANK)
+            .unwrap_or(true);
+        let brightness = read_trimmed(dir, "brightness")
+            .and_then(|b| b.parse::<u64>().ok())
+            .unwrap_or(0);
+        let on = powered && brightness > 0;
+        if on {
+            trace!("Backlight '{}' is on.", dir.display());
+        }
+        on
+    }))
+}
+
+fn any_connector_on(class_dir: &Path) -> Option<bool> {
+    // Connectors are named e.g. card0-DSI-1, cards themselves card0.
+    let connected: Vec<PathBuf> = subdirs(class_dir)
+        .into_iter()
+        .filter(|dir| {
+            dir.file_name()
+                .map(|name| name.to_string_lossy().contains('-'))
+                .unwrap_or(false)
+        })
+        .filter(|dir| read_trimmed(dir, "status").as_deref() == Some("connected"))
+        .collect();
+    if connected.is_empty() {
+        return None;
+    }
+    Some(connected.iter().any(|dir| {
+        let on = read_trimmed(dir, "enabled").as_deref() != Some("disabled")
+            && read_trimmed(dir, "dpms").as_deref() == Some("On");
+        if on {
+            trace!("Display connector '{}' is on.", dir.display());
+        }
+        on
+    }))
+}
+
+fn subdirs(dir: &Path) -> Vec<PathBuf> {
+    match fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+        Err(e) => {
+            debug!("Failed to read '{}': {}", dir.display(), e);
+            Vec::new()
+        }
+    }
+}
+
+fn read_trimmed(dir: &Path, file: &str) -> Option<String> {
+    fs::read_to_string(dir.join(file))
+        .ok()
+        .map(|content| content.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::temp_dir;
+
+    fn write_attrs(dir: &Path, attrs: &[(&str, &str)]) {
+        fs::create_dir_all(dir).unwrap();
+        for (file, content) in attrs {
+            fs::write(dir.join(file), format!("{content}\n")).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_any_backlight_on() {
+        let dir = temp_dir("backlight_on");
+        assert_eq!(any_backlight_on(&dir), None);
+        let backlight = dir.join("backlight");
+        write_attrs(&backlight, &[("bl_power", "0"), ("brightness", "0")]);
+        assert_eq!(any_backlight_on(&dir), Some(false));
+        write_attrs(&backlight, &[("bl_power", "4"), ("brightness", "800")]);
+        assert_eq!(any_backlight_on(&dir), Some(false));
+        write_attrs(&backlight, &[("bl_power", "0"), ("brightness", "800")]);
+        assert_eq!(any_backlight_on(&dir), Some(true));
+    }
+
+    #[test]
+    fn test_any_connector_on() {
+        let dir = temp_dir("display_on");
+        write_attrs(&dir.join("card0"), &[]);
+        write_attrs(
+            &dir.join("card0-HDMI-A-1"),
+            &[("status", "disconnected"), ("dpms", "Off")],
+        );
+        assert_eq!(any_connector_on(&dir), None);
+        let dsi = dir.join("card0-DSI-1");
+        write_attrs(
+            &dsi,
+            &[
+                ("status", "connected"),
+                ("enabled", "enabled"),
+                ("dpms", "Off"),
+            ],
+        );
+        assert_eq!(any_connector_on(&dir), Some(false));
+        write_attrs(&dsi, &[("dpms", "On")]);
+        assert_eq!(any_connector_on(&dir), Some(true));
+    }
+}
@@ -0,0 +1,69 @@
+use crate::core::vars::VarValue;
+use crate::var_creation_context::VarCreationContext;
+use crate::var_fns::PollVarFns;
+use anyhow::{anyhow, Error as AnyError};
+use async_trait::async_trait;
+use log::{trace, warn};
+use serde_yaml::Value;
+use std::collections::HashMap;
+use zbus::Connection as ZbusConnection;
+
+/// Reports whether the lid switch is closed, according to the login
+/// manager. Undefined when the device has no lid.
+#[derive(Clone, Debug)]
+pub struct LidClosedFns {
+    system_dbus_conn: ZbusConnection,
+}
+
+impl LidClosedFns {
+    pub fn new(
+        _params: &HashMap<String, Value>,
+        context: &VarCreationContext,
+    ) -> Result<Self, AnyError> {
+        Ok(Self {
+            system_dbus_conn: context.system_dbus_conn()?,
+        })
+    }
+}
+
+#[async_trait]
+impl PollVarFns for LidClosedFns {
+    async fn poll(&mut self) -> Option<VarValue> {
+        let lid_present = login_manager_bool_property(&self.system_dbus_conn, "LidPresent")
+            .await
+            .map_err(|e| warn!("Failed to fetch login manager property LidPresent: {:#}", e))
+            .ok()?;
+        if !lid_present {
+            trace!("Device has no lid.");
+            return None;
+        }
+        login_manager_bool_property(&self.system_dbus_conn, "LidClosed")
+            .await
+            .map_err(|e| warn!("Failed to fetch login manager property LidClosed: {:#}", e))
+            .ok()
+            .map(VarValue::Bool)
+    }
+}
+
+async fn login_manager_bool_property(
+    system_dbus_conn: &ZbusConnection,
+    property: &str,
+) -> Result<bool, AnyError> {
+    let msg = system_dbus_conn
+        .call_method(
+            Some("org.freedesktop.login1"),
+            "/org/freedesktop/login1",
+            Some("org.freedesktop.DBus.Properties"),
+            "Get",
+            &["org.freedesktop.login1.Manager", property],
+        )
+        .await?;
+    let value: zvariant::OwnedValue = msg.body()?;
+    match &*value {
+        zvariant::Value::Bool(value) => Ok(*value),
+        _ => Err(anyhow!(
+            "Wrong data type of login manager property {}.",
+            property
+        )),
+    }
+}
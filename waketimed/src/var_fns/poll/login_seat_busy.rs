@@ -1,66 +1,328 @@
-use crate::core::vars::VarValue;
+use crate::core::vars::{param_optional, VarValue};
+use crate::time;
 use crate::var_creation_context::VarCreationContext;
 use crate::var_fns::PollVarFns;
 use anyhow::{anyhow, Error as AnyError};
 use async_trait::async_trait;
-use log::warn;
+use log::{trace, warn};
+use serde_derive::Deserialize;
 use serde_yaml::Value;
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::time::Duration;
 
 use zbus::Connection as ZbusConnection;
 
+const LOGIN1_DEST: &str = "org.freedesktop.login1";
+const LOGIN1_PATH: &str = "/org/freedesktop/login1";
+const LOGIN1_MANAGER_INTERFACE: &str = "org.freedesktop.login1.Manager";
+const LOGIN1_SEAT_INTERFACE: &str = "org.freedesktop.login1.Seat";
+const LOGIN1_SESSION_INTERFACE: &str = "org.freedesktop.login1.Session";
+// Manual for sd-login.h states that seat0 always exists.
+// https://www.freedesktop.org/software/systemd/man/sd-login.html
+const DEFAULT_SEAT: &str = "seat0";
+
+/// Which value the poll var reports.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoginSeatVar {
+    /// Whether any selected seat is busy (not idle).
+    Busy,
+    /// For how long all selected seats have been idle, in
+    /// milliseconds.
+    IdleMs,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum SeatSelection {
+    All,
+    Names(Vec<String>),
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+enum SeatsParam {
+    Keyword(String),
+    Names(Vec<String>),
+}
+
 #[derive(Clone, Debug)]
 pub struct LoginSeatBusyFns {
+    var: LoginSeatVar,
     system_dbus_conn: ZbusConnection,
+    seats: SeatSelection,
+    // Look at IdleHint of each session on the seats rather than the
+    // seat's own IdleHint.
+    sessions: bool,
+    // A seat counts as busy until it has been idle for this long.
+    min_idle: Duration,
+    // Object paths of seats selected by name, resolved on first
+    // successful poll.
+    named_seat_paths: Option<Vec<String>>,
 }
 
 impl LoginSeatBusyFns {
     pub fn new(
-        _params: &HashMap<String, Value>,
+        var: LoginSeatVar,
+        params: &HashMap<String, Value>,
         context: &VarCreationContext,
     ) -> Result<Self, AnyError> {
+        let seats = match param_optional::<SeatsParam>(params, "seats")? {
+            None => SeatSelection::Names(vec![DEFAULT_SEAT.to_string()]),
+            Some(SeatsParam::Keyword(keyword)) if keyword == "all" => SeatSelection::All,
+            Some(SeatsParam::Keyword(keyword)) => {
+                return Err(anyhow!(
+                    "Var parameter 'seats' must be a list of seat names or \"all\", got \"{}\".",
+                    keyword
+                ))
+            }
+            Some(SeatsParam::Names(names)) => SeatSelection::Names(names),
+        };
         Ok(Self {
+            var,
             system_dbus_conn: context.system_dbus_conn()?,
+            seats,
+            sessions: param_optional::<bool>(params, "sessions")?.unwrap_or(false),
+            min_idle: Duration::from_millis(
+                param_optional::<u64>(params, "min_idle")?.unwrap_or(0),
+            ),
+            named_seat_paths: None,
         })
     }
+
+    /// Whether idle durations are needed, rather than just the idle
+    /// hints.
+    fn needs_idle_since(&self) -> bool {
+        self.var == LoginSeatVar::IdleMs || !self.min_idle.is_zero()
+    }
+
+    async fn seat_paths(&mut self) -> Result<Vec<String>, AnyError> {
+        let conn = &self.system_dbus_conn;
+        let names = match &self.seats {
+            SeatSelection::All => return list_seat_paths(conn).await,
+            SeatSelection::Names(names) => names,
+        };
+        if let Some(paths) = self.named_seat_paths.as_ref() {
+            return Ok(paths.clone());
+        }
+        let mut paths = Vec::new();
+        for name in names.iter() {
+            paths.push(seat_path(conn, name).await?);
+        }
+        self.named_seat_paths = Some(paths.clone());
+        Ok(paths)
+    }
+
+    async fn fetch_idle_hints(&mut self) -> Result<Vec<IdleHint>, AnyError> {
+        let seat_paths = self.seat_paths().await?;
+        let conn = &self.system_dbus_conn;
+        let mut object_paths = Vec::new();
+        if self.sessions {
+            for seat_path in seat_paths.iter() {
+                object_paths.extend(
+                    seat_session_paths(conn, seat_path)
+                        .await?
+                        .into_iter()
+                        .map(|path| (path, LOGIN1_SESSION_INTERFACE)),
+                );
+            }
+        } else {
+            object_paths.extend(
+                seat_paths
+                    .into_iter()
+                    .map(|path| (path, LOGIN1_SEAT_INTERFACE)),
+            );
+        }
+        let with_since = self.needs_idle_since();
+        let mut hints = Vec::new();
+        for (path, interface) in object_paths.iter() {
+            hints.push(fetch_idle_hint(conn, path, interface, with_since).await?);
+        }
+        Ok(hints)
+    }
 }
 
 #[async_trait]
 impl PollVarFns for LoginSeatBusyFns {
     async fn poll(&mut self) -> Option<VarValue> {
-        let system_dbus_conn = self.system_dbus_conn.clone();
-        // NOTE: It's unclear whether there's any practical use to
-        // look up multiple seats. For now we assume a single seat
-        // device. Manual for sd-login.h states that seat0 always
-        // exists.
-        // https://www.freedesktop.org/software/systemd/man/sd-login.html
-        let idle_hint_res = system_dbus_conn
-            .call_method(
-                Some("org.freedesktop.login1"),
-                "/org/freedesktop/login1/seat/seat0",
-                Some("org.freedesktop.DBus.Properties"),
-                "Get",
-                &["org.freedesktop.login1.Seat", "IdleHint"],
-            )
-            .await;
-        process_call_result(idle_hint_res)
-            .map_err(|e| warn!("Failed to fetch login manager property IdleHint: {:?}", e))
-            .ok()
+        let hints = self
+            .fetch_idle_hints()
+            .await
+            .map_err(|e| {
+                warn!("Failed to fetch login manager idle hints: {:#}", e);
+                // A seat may have been removed, resolve paths again.
+                self.named_seat_paths = None;
+            })
+            .ok()?;
+        let now = time::monotonic_now()
+            .map_err(|e| warn!("Failed to read monotonic clock: {:#}", e))
+            .ok()?;
+        trace!("Login manager idle hints: {:?}", hints);
+        let idle_for = min_idle_duration(&hints, now);
+        match self.var {
+            // Using "busy" rather than login manager's "idle" because
+            // we typically want truthy values for "block suspend
+            // when ...".
+            LoginSeatVar::Busy => Some(VarValue::Bool(
+                idle_for
+                    .map(|d| d.is_zero() || d < self.min_idle)
+                    .unwrap_or(false),
+            )),
+            LoginSeatVar::IdleMs => idle_for
+                .and_then(|d| d.as_millis().try_into().ok())
+                .map(VarValue::Int),
+        }
     }
 }
 
-fn process_call_result(
-    idle_hint_res: Result<Arc<zbus::Message>, zbus::Error>,
-) -> Result<VarValue, AnyError> {
-    let idle_hint_msg = idle_hint_res?;
-    let body_value: zvariant::Value = idle_hint_msg.body()?;
-    if let zvariant::Value::Bool(idle_hint) = body_value {
-        // Using ! operator because the semantics is inverted. Login
-        // manager uses "idle", we use "busy" because we typically
-        // want truthy values for "block suspend when ...".
-        Ok(VarValue::Bool(!idle_hint))
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct IdleHint {
+    idle: bool,
+    // CLOCK_MONOTONIC time when the seat or session became idle, None
+    // if unknown or not fetched.
+    idle_since_monotonic: Option<Duration>,
+}
+
+/// Shortest idle duration among seats or sessions, zero if any of
+/// them is busy. Ones idle since an unknown time are assumed to be
+/// idle for long, None if there are no others.
+fn min_idle_duration(hints: &[IdleHint], now: Duration) -> Option<Duration> {
+    hints
+        .iter()
+        .filter_map(|hint| {
+            if !hint.idle {
+                Some(Duration::ZERO)
+            } else {
+                hint.idle_since_monotonic
+                    .map(|since| now.saturating_sub(since))
+            }
+        })
+        .min()
+}
+
+async fn list_seat_paths(conn: &ZbusConnection) -> Result<Vec<String>, AnyError> {
+    let msg = conn
+        .call_method(
+            Some(LOGIN1_DEST),
+            LOGIN1_PATH,
+            Some(LOGIN1_MANAGER_INTERFACE),
+            "ListSeats",
+            &(),
+        )
+        .await?;
+    // (seat id, seat object path)
+    let seats: Vec<(String, zvariant::OwnedObjectPath)> = msg.body()?;
+    Ok(seats
+        .into_iter()
+        .map(|(_, path)| path.to_string())
+        .collect())
+}
+
+async fn seat_path(conn: &ZbusConnection, seat_name: &str) -> Result<String, AnyError> {
+    let msg = conn
+        .call_method(
+            Some(LOGIN1_DEST),
+            LOGIN1_PATH,
+            Some(LOGIN1_MANAGER_INTERFACE),
+            "GetSeat",
+            &(seat_name,),
+        )
+        .await?;
+    let path: zvariant::OwnedObjectPath = msg.body()?;
+    Ok(path.to_string())
+}
+
+async fn seat_session_paths(
+    conn: &ZbusConnection,
+    seat_path: &str,
+) -> Result<Vec<String>, AnyError> {
+    let value = get_property(conn, seat_path, LOGIN1_SEAT_INTERFACE, "Sessions").await?;
+    // (session id, session object path)
+    let sessions: Vec<(String, zvariant::OwnedObjectPath)> = value.try_into()?;
+    Ok(sessions
+        .into_iter()
+        .map(|(_, path)| path.to_string())
+        .collect())
+}
+
+async fn fetch_idle_hint(
+    conn: &ZbusConnection,
+    path: &str,
+    interface: &str,
+    with_since: bool,
+) -> Result<IdleHint, AnyError> {
+    let idle = match &*get_property(conn, path, interface, "IdleHint").await? {
+        zvariant::Value::Bool(idle) => *idle,
+        _ => return Err(anyhow!("Wrong data type of IdleHint.")),
+    };
+    // Only needed when idle, 0 means unknown.
+    let since = if with_since && idle {
+        match &*get_property(conn, path, interface, "IdleSinceHintMonotonic").await? {
+            zvariant::Value::U64(0) => None,
+            zvariant::Value::U64(usec) => Some(Duration::from_micros(*usec)),
+            _ => return Err(anyhow!("Wrong data type of IdleSinceHintMonotonic.")),
+        }
     } else {
-        Err(anyhow!("Wrong data type."))
+        None
+    };
+    Ok(IdleHint {
+        idle,
+        idle_since_monotonic: since,
+    })
+}
+
+async fn get_property(
+    conn: &ZbusConnection,
+    path: &str,
+    interface: &str,
+    property: &str,
+) -> Result<zvariant::OwnedValue, AnyError> {
+    let msg = conn
+        .call_method(
+            Some(LOGIN1_DEST),
+            path,
+            Some("org.freedesktop.DBus.Properties"),
+            "Get",
+            &[interface, property],
+        )
+        .await?;
+    Ok(msg.body()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_min_idle_duration() {
+        let secs = Duration::from_secs;
+        let hint = |idle: bool, since: u64| IdleHint {
+            idle,
+            idle_since_monotonic: Some(secs(since)).filter(|since| !since.is_zero()),
+        };
+        let now = secs(1000);
+        assert_eq!(min_idle_duration(&[], now), None);
+        assert_eq!(
+            min_idle_duration(&[hint(true, 900), hint(true, 400)], now),
+            Some(secs(100))
+        );
+        assert_eq!(
+            min_idle_duration(&[hint(true, 900), hint(false, 0)], now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(min_idle_duration(&[hint(true, 0)], now), None);
+        assert_eq!(
+            min_idle_duration(&[hint(true, 0), hint(true, 900)], now),
+            Some(secs(100))
+        );
+    }
+
+    #[test]
+    fn test_seats_param() {
+        let params = |yaml: &str| -> HashMap<String, Value> { serde_yaml::from_str(yaml).unwrap() };
+        let seats = |yaml: &str| param_optional::<SeatsParam>(&params(yaml), "seats").unwrap();
+        assert!(matches!(seats("seats: all"), Some(SeatsParam::Keyword(k)) if k == "all"));
+        assert!(
+            matches!(seats("seats: [seat0, seat1]"), Some(SeatsParam::Names(n)) if n.len() == 2)
+        );
+        assert!(seats("{}").is_none());
     }
 }
@@ -0,0 +1,130 @@
+use crate::core::vars::VarValue;
+use crate::user_buses::UserBuses;
+use crate::var_creation_context::VarCreationContext;
+use crate::var_fns::PollVarFns;
+use anyhow::{anyhow, Error as AnyError};
+use async_trait::async_trait;
+use log::{debug, trace, warn};
+use serde_yaml::Value;
+use std::collections::HashMap;
+use zbus::Connection as ZbusConnection;
+
+const MPRIS_NAME_PREFIX: &str = "org.mpris.MediaPlayer2.";
+const MPRIS_OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+const MPRIS_PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+
+#[derive(Clone, Debug)]
+pub struct MediaPlayingFns {
+    user_buses: UserBuses,
+}
+
+impl MediaPlayingFns {
+    pub fn new(
+        _params: &HashMap<String, Value>,
+        context: &VarCreationContext,
+    ) -> Result<Self, AnyError> {
+        Ok(Self {
+            user_buses: context.user_buses()?,
+        })
+    }
+}
+
+#[async_trait]
+impl PollVarFns for MediaPlayingFns {
+    async fn poll(&mut self) -> Option<VarValue> {
+        let user_conns = self
+            .user_buses
+            .active_connections()
+            .await
+            .map_err(|e| warn!("Failed to get user session buses: {:#}", e))
+            .ok()?;
+        for (uid, conn) in user_conns.iter() {
+            match user_media_playing(conn).await {
+                Ok(true) => return Some(VarValue::Bool(true)),
+                Ok(false) => {}
+                Err(e) => {
+                    debug!("Failed to check media players of user {}: {:#}", uid, e);
+                    // The user's bus may have been restarted, reconnect
+                    // on next poll. Failing reconnects get reported by
+                    // UserBuses.
+                    self.user_buses.forget(*uid).await;
+                }
+            }
+        }
+        Some(VarValue::Bool(false))
+    }
+}
+
+async fn user_media_playing(conn: &ZbusConnection) -> Result<bool, AnyError> {
+    let list_names_msg = conn
+        .call_method(
+            Some("org.freedesktop.DBus"),
+            "/org/freedesktop/DBus",
+            Some("org.freedesktop.DBus"),
+            "ListNames",
+            &(),
+        )
+        .await?;
+    let names: Vec<String> = list_names_msg.body()?;
+    for player in names.iter().filter(|name| is_mpris_player_name(name)) {
+        // A single misbehaving player shouldn't hide the others.
+        match player_playback_status(conn, player).await {
+            Ok(status) => {
+                trace!("Media player '{}' status: {}", player, status);
+                if is_playing(&status) {
+                    return Ok(true);
+                }
+            }
+            Err(e) => trace!(
+                "Failed to get playback status of media player '{}': {:#}",
+                player,
+                e
+            ),
+        }
+    }
+    Ok(false)
+}
+
+async fn player_playback_status(conn: &ZbusConnection, player: &str) -> Result<String, AnyError> {
+    let status_msg = conn
+        .call_method(
+            Some(player),
+            MPRIS_OBJECT_PATH,
+            Some("org.freedesktop.DBus.Properties"),
+            "Get",
+            &[MPRIS_PLAYER_INTERFACE, "PlaybackStatus"],
+        )
+        .await?;
+    let body_value: zvariant::Value = status_msg.body()?;
+    if let zvariant::Value::Str(status) = body_value {
+        Ok(status.to_string())
+    } else {
+        Err(anyhow!("Wrong data type."))
+    }
+}
+
+fn is_mpris_player_name(name: &str) -> bool {
+    name.len() > MPRIS_NAME_PREFIX.len() && name.starts_with(MPRIS_NAME_PREFIX)
+}
+
+fn is_playing(playback_status: &str) -> bool {
+    playback_status == "Playing"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_mpris_player_name() {
+        assert!(is_mpris_player_name("org.mpris.MediaPlayer2.vlc"));
+        assert!(is_mpris_player_name(
+            "org.mpris.MediaPlayer2.firefox.instance_1_42"
+        ));
+        assert!(!is_mpris_player_name("org.mpris.MediaPlayer2."));
+        assert!(!is_mpris_player_name("org.freedesktop.Notifications"));
+        assert!(!is_playing("Paused"));
+        assert!(!is_playing("Stopped"));
+        assert!(is_playing("Playing"));
+    }
+}
@@ -1,6 +1,21 @@
+pub mod audio_playback_active;
+pub mod bluetooth;
+pub mod display;
+pub mod lid_closed;
 pub mod login_seat_busy;
+pub mod media_playing;
+pub mod modem_sms_received;
 pub mod modem_voice_call_present;
+pub mod network_busy;
+pub mod network_manager;
+pub mod power_supply;
+pub mod process_running;
+pub mod remote_session;
 pub mod sleep_block_inhibited;
+pub mod system_load;
+pub mod systemd_activity;
+pub mod thermal;
+pub mod wakelocks;
 
 pub mod test_inactive;
 pub mod test_poll_bool;
@@ -0,0 +1,288 @@
+use crate::core::vars::{param_optional, VarValue};
+use crate::var_creation_context::VarCreationContext;
+use crate::var_fns::PollVarFns;
+use anyhow::{anyhow, Error as AnyError};
+use async_trait::async_trait;
+use futures_util::future::FutureExt;
+use futures_util::stream::StreamExt;
+use log::{debug, trace, warn};
+use serde_yaml::Value;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+use zbus::{Connection as ZbusConnection, MatchRule, MessageStream, MessageType};
+
+const MM_DEST: &str = "org.freedesktop.ModemManager1";
+const MM_PATH: &str = "/org/freedesktop/ModemManager1";
+const MM_MESSAGING_INTERFACE: &str = "org.freedesktop.ModemManager1.Modem.Messaging";
+const MM_SMS_INTERFACE: &str = "org.freedesktop.ModemManager1.Sms";
+// MMSmsState values.
+const SMS_STATE_RECEIVING: u32 = 2;
+const SMS_STATE_RECEIVED: u32 = 3;
+
+/// Reports true while an SMS is being received, and for a grace
+/// period after an SMS was received, so that messaging apps have time
+/// to fetch it.
+pub struct ModemSmsReceivedFns {
+    system_dbus_conn: ZbusConnection,
+    // Subscribed lazily on first poll. None if subscribing failed,
+    // then it is retried on the next poll.
+    added_signals: Option<MessageStream>,
+    tracker: SmsEventTracker,
+}
+
+impl ModemSmsReceivedFns {
+    pub fn new(
+        params: &HashMap<String, Value>,
+        context: &VarCreationContext,
+    ) -> Result<Self, AnyError> {
+        let grace_period = param_optional::<u64>(params, "grace_period")?.unwrap_or(15_000);
+        Ok(Self {
+            system_dbus_conn: context.system_dbus_conn()?,
+            added_signals: None,
+            tracker: SmsEventTracker::new(Duration::from_millis(grace_period)),
+        })
+    }
+
+    async fn subscribe_added_signals(&mut self) {
+        if self.added_signals.is_some() {
+            return;
+        }
+        match added_signal_stream(&self.system_dbus_conn).await {
+            Ok(stream) => {
+                debug!("Subscribed to modem Messaging Added signals.");
+                self.added_signals = Some(stream);
+            }
+            Err(e) => warn!(
+                "Failed to subscribe to modem Messaging Added signals: {:#}",
+                e
+            ),
+        }
+    }
+
+    /// Process Added signals received since the previous poll, without
+    /// waiting for new ones.
+    fn process_added_signals(&mut self, now: Instant) {
+        let stream = match self.added_signals.as_mut() {
+            Some(stream) => stream,
+            None => return,
+        };
+        while let Some(Some(msg_res)) = stream.next().now_or_never() {
+            let received = msg_res
+                .map_err(AnyError::from)
+                .and_then(|msg| Ok(msg.body::<(zvariant::OwnedObjectPath, bool)>()?))
+                .map_err(|e| warn!("Failed to process modem Messaging Added signal: {:#}", e));
+            if let Ok((sms_path, true)) = received {
+                trace!("SMS '{}' added as received.", sms_path.as_str());
+                self.tracker.record_event(now);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl PollVarFns for ModemSmsReceivedFns {
+    async fn poll(&mut self) -> Option<VarValue> {
+        self.subscribe_added_signals().await;
+        let now = Instant::now();
+        self.process_added_signals(now);
+        // Signals may be missed e.g. when ModemManager restarts, so
+        // also look at the SMS objects themselves.
+        let sms_states = fetch_sms_states(&self.system_dbus_conn)
+            .await
+            .map_err(|e| {
+                warn!("Failed to fetch modem SMS states: {:#}", e);
+                // ModemManager may be restarting, SMS paths can change.
+                self.tracker.forget_sms_states();
+            })
+            .ok()?;
+        trace!("SMS states: {:?}", sms_states);
+        let receiving = self.tracker.update_sms_states(now, &sms_states);
+        Some(VarValue::Bool(
+            receiving || self.tracker.in_grace_period(now),
+        ))
+    }
+}
+
+async fn added_signal_stream(system_dbus_conn: &ZbusConnection) -> Result<MessageStream, AnyError> {
+    // Modem paths change during runtime, so match on any path.
+    let rule = MatchRule::builder()
+        .msg_type(MessageType::Signal)
+        .sender(MM_DEST)?
+        .interface(MM_MESSAGING_INTERFACE)?
+        .member("Added")?
+        .build();
+    Ok(MessageStream::for_match_rule(rule, system_dbus_conn, None).await?)
+}
+
+/// Fetch states of all SMS objects of all modems, as (SMS path, state).
+async fn fetch_sms_states(
+    system_dbus_conn: &ZbusConnection,
+) -> Result<Vec<(String, u32)>, AnyError> {
+    let objects_msg = system_dbus_conn
+        .call_method(
+            Some(MM_DEST),
+            MM_PATH,
+            Some("org.freedesktop.DBus.ObjectManager"),
+            "GetManagedObjects",
+            &(),
+        )
+        .await?;
+    let objects: HashMap<
+        zvariant::OwnedObjectPath,
+        HashMap<String, HashMap<String, zvariant::OwnedValue>>,
+    > = objects_msg.body()?;
+    let mut states = Vec::new();
+    for sms_path in sms_paths(&objects) {
+        let state_msg = system_dbus_conn
+            .call_method(
+                Some(MM_DEST),
+                sms_path.as_str(),
+                Some("org.freedesktop.DBus.Properties"),
+                "Get",
+                &[MM_SMS_INTERFACE, "State"],
+            )
+            .await?;
+        match state_msg.body::<zvariant::Value>()? {
+            zvariant::Value::U32(state) => states.push((sms_path, state)),
+            _ => return Err(anyhow!("Wrong data type of SMS State.")),
+        }
+    }
+    Ok(states)
+}
+
+/// Paths of SMS objects listed in the Messages property of modems'
+/// Messaging interface.
+fn sms_paths(
+    objects: &HashMap<
+        zvariant::OwnedObjectPath,
+        HashMap<String, HashMap<String, zvariant::OwnedValue>>,
+    >,
+) -> Vec<String> {
+    objects
+        .values()
+        .filter_map(|ifaces| ifaces.get(MM_MESSAGING_INTERFACE))
+        .filter_map(|props| props.get("Messages"))
+        .filter_map(|messages| match &**messages {
+            zvariant::Value::Array(paths) => Some(
+                paths
+                    .iter()
+                    .filter_map(|path| match path {
+                        zvariant::Value::ObjectPath(path) => Some(path.to_string()),
+                        _ => None,
+                    })
+                    .collect::<Vec<String>>(),
+            ),
+            _ => None,
+        })
+        .flatten()
+        .collect()
+}
+
+#[derive(Clone, Debug)]
+struct SmsEventTracker {
+    grace_period: Duration,
+    last_event: Option<Instant>,
+    // Received SMS objects seen on previous polls. None until the
+    // first successful fetch, as SMS objects which exist at that point
+    // were not received just now.
+    seen_received: Option<HashSet<String>>,
+}
+
+impl SmsEventTracker {
+    fn new(grace_period: Duration) -> Self {
+        Self {
+            grace_period,
+            last_event: None,
+            seen_received: None,
+        }
+    }
+
+    fn record_event(&mut self, now: Instant) {
+        self.last_event = Some(now);
+    }
+
+    /// Record newly appeared received SMS objects as events. Returns
+    /// whether any SMS is still being received.
+    fn update_sms_states(&mut self, now: Instant, sms_states: &[(String, u32)]) -> bool {
+        let received: HashSet<String> = sms_states
+            .iter()
+            .filter(|(_, state)| *state == SMS_STATE_RECEIVED)
+            .map(|(path, _)| path.clone())
+            .collect();
+        if let Some(seen_received) = self.seen_received.as_ref() {
+            if received.difference(seen_received).next().is_some() {
+                self.record_event(now);
+            }
+        }
+        self.seen_received = Some(received);
+        sms_states
+            .iter()
+            .any(|(_, state)| *state == SMS_STATE_RECEIVING)
+    }
+
+    /// Start over as if SMS states were never fetched.
+    fn forget_sms_states(&mut self) {
+        self.seen_received = None;
+    }
+
+    fn in_grace_period(&self, now: Instant) -> bool {
+        self.last_event
+            .map(|last_event| now.duration_since(last_event) < self.grace_period)
+            .unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sms_event_tracker() {
+        let start = Instant::now();
+        let secs = |s: u64| start + Duration::from_secs(s);
+        let sms = |path: &str, state: u32| (path.to_string(), state);
+        let mut tracker = SmsEventTracker::new(Duration::from_secs(10));
+        assert!(!tracker.in_grace_period(start));
+
+        // A multipart SMS is being received.
+        assert!(tracker.update_sms_states(start, &[sms("/sms/1", SMS_STATE_RECEIVING)]));
+        assert!(!tracker.in_grace_period(start));
+
+        // Fully received, grace period starts.
+        assert!(!tracker.update_sms_states(secs(1), &[sms("/sms/1", SMS_STATE_RECEIVED)]));
+        assert!(tracker.in_grace_period(secs(10)));
+        // An already seen SMS doesn't extend the grace period.
+        tracker.update_sms_states(secs(5), &[sms("/sms/1", SMS_STATE_RECEIVED)]);
+        assert!(!tracker.in_grace_period(secs(11)));
+
+        // Added signal starts another grace period.
+        tracker.record_event(secs(20));
+        assert!(tracker.in_grace_period(secs(29)));
+        assert!(!tracker.in_grace_period(secs(30)));
+    }
+
+    #[test]
+    fn test_sms_event_tracker_seeding() {
+        let start = Instant::now();
+        let secs = |s: u64| start + Duration::from_secs(s);
+        let sms = |path: &str, state: u32| (path.to_string(), state);
+        let mut tracker = SmsEventTracker::new(Duration::from_secs(10));
+
+        // SMS already stored on the modem are not new.
+        tracker.update_sms_states(start, &[sms("/sms/1", SMS_STATE_RECEIVED)]);
+        assert!(!tracker.in_grace_period(start));
+        tracker.update_sms_states(
+            secs(1),
+            &[
+                sms("/sms/1", SMS_STATE_RECEIVED),
+                sms("/sms/2", SMS_STATE_RECEIVED),
+            ],
+        );
+        assert!(tracker.in_grace_period(secs(1)));
+
+        // After e.g. ModemManager restart, paths are seeded again.
+        tracker.forget_sms_states();
+        tracker.update_sms_states(secs(20), &[sms("/sms/7", SMS_STATE_RECEIVED)]);
+        assert!(!tracker.in_grace_period(secs(20)));
+    }
+}
@@ -3,15 +3,26 @@ use crate::var_creation_context::VarCreationContext;
 use crate::var_fns::PollVarFns;
 use anyhow::Error as AnyError;
 use async_trait::async_trait;
-use log::{trace, warn};
+use futures_util::future::FutureExt;
+use futures_util::stream::StreamExt;
+use log::{debug, trace, warn};
 use serde_yaml::Value;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use zbus::Connection as ZbusConnection;
+use zbus::{Connection as ZbusConnection, MatchRule, MessageStream, MessageType};
+
+const MM_DEST: &str = "org.freedesktop.ModemManager1";
+const MM_PATH: &str = "/org/freedesktop/ModemManager1";
+const MM_VOICE_INTERFACE: &str = "org.freedesktop.ModemManager1.Modem.Voice";
+const OBJECT_MANAGER_INTERFACE: &str = "org.freedesktop.DBus.ObjectManager";
 
-#[derive(Clone, Debug)]
 pub struct ModemVoiceCallPresentFns {
     system_dbus_conn: ZbusConnection,
+    // None until subscribed. Without signals, every poll does a full
+    // rescan.
+    signals: Option<ModemSignals>,
+    calls: VoiceCalls,
+    needs_rescan: bool,
 }
 
 impl ModemVoiceCallPresentFns {
@@ -21,49 +32,278 @@ impl ModemVoiceCallPresentFns {
     ) -> Result<Self, AnyError> {
         Ok(Self {
             system_dbus_conn: context.system_dbus_conn()?,
+            signals: None,
+            calls: VoiceCalls::default(),
+            needs_rescan: true,
         })
     }
+
+    async fn subscribe_signals(&mut self) {
+        if self.signals.is_some() {
+            return;
+        }
+        match ModemSignals::subscribe(&self.system_dbus_conn).await {
+            Ok(signals) => {
+                debug!("Subscribed to modem manager voice signals.");
+                self.signals = Some(signals);
+                // Calls may have changed while not subscribed.
+                self.needs_rescan = true;
+            }
+            Err(e) => warn!("Failed to subscribe to modem manager signals: {:#}", e),
+        }
+    }
+
+    /// Apply signals received since the previous poll to the tracked
+    /// calls. Modems added meanwhile get their calls listed.
+    async fn process_signals(&mut self) {
+        let signals = match self.signals.as_mut() {
+            Some(signals) => signals,
+            None => return,
+        };
+        let mut events = Vec::new();
+        let mut stream_failed = false;
+        // How a modem is referred to routinely changes during runtime,
+        // so modems are tracked via signals rather than cached once.
+        // https://github.com/jistr/waketimed/issues/5
+        for stream in signals.streams_mut() {
+            while let Some(msg_opt) = stream.next().now_or_never() {
+                match msg_opt.map(|msg_res| msg_res.map_err(AnyError::from).and_then(parse_signal))
+                {
+                    Some(Ok(Some(event))) => events.push(event),
+                    Some(Ok(None)) => {}
+                    Some(Err(e)) => {
+                        warn!("Failed to process modem manager signal: {:#}", e);
+                        stream_failed = true;
+                    }
+                    None => {
+                        warn!("Modem manager signal stream ended.");
+                        stream_failed = true;
+                        break;
+                    }
+                }
+            }
+        }
+        if stream_failed {
+            // Resubscribe and rescan on next poll.
+            self.signals = None;
+            self.needs_rescan = true;
+            return;
+        }
+        for event in events {
+            trace!("Modem manager event: {:?}", event);
+            if let SignalEvent::VoiceModemAdded(modem) = &event {
+                match list_calls(&self.system_dbus_conn, modem).await {
+                    Ok(calls) => self.calls.set_modem_calls(modem, calls),
+                    Err(e) => {
+                        warn!("Failed to call modem manager ListCalls: {:#}", e);
+                        self.needs_rescan = true;
+                    }
+                }
+            } else if !self.calls.apply(event) {
+                self.needs_rescan = true;
+            }
+        }
+    }
+
+    async fn rescan(&mut self) -> Result<(), AnyError> {
+        let voice_devices = fetch_voice_devices(&self.system_dbus_conn).await?;
+        trace!("List of voice devices: {:?}", voice_devices);
+        let mut calls = VoiceCalls::default();
+        for device in voice_devices.iter() {
+            calls.set_modem_calls(device, list_calls(&self.system_dbus_conn, device).await?);
+        }
+        self.calls = calls;
+        self.needs_rescan = false;
+        Ok(())
+    }
 }
 
 #[async_trait]
 impl PollVarFns for ModemVoiceCallPresentFns {
     async fn poll(&mut self) -> Option<VarValue> {
-        let system_dbus_conn = self.system_dbus_conn.clone();
+        self.subscribe_signals().await;
+        self.process_signals().await;
+        if self.needs_rescan || self.signals.is_none() {
+            debug!("Rescanning modem manager voice calls.");
+            self.rescan()
+                .await
+                .map_err(|e| warn!("Failed to list modem manager voice calls: {:#}", e))
+                .ok()?;
+        }
+        trace!("Voice calls: {:?}", self.calls);
+        Some(VarValue::Bool(self.calls.any_present()))
+    }
+}
 
-        // How a modem is referred to routinely changes during runtime, do not cache this.
-        // https://github.com/jistr/waketimed/issues/5
-        let voice_devices_opt = fetch_voice_devices(&system_dbus_conn).await;
-        trace!("List of voice devices: {:?}", voice_devices_opt);
-        let voice_devices = voice_devices_opt?;
-
-        // Do something smarter when async_iter is stable:
-        // https://doc.rust-lang.org/std/async_iter/index.html
-        // E.g.:
-        // let devices_have_calls: Vec<bool> = voice_devices.iter().map(|d| {
-        //     device_has_calls_present(&system_dbus_conn, d)
-        // }).collect();
-        let mut devices_have_calls: Vec<bool> = Vec::new();
-        for device in voice_devices.iter() {
-            devices_have_calls.push(device_has_calls_present(&system_dbus_conn, device).await);
+/// Signal subscriptions needed to track voice calls incrementally.
+struct ModemSignals {
+    // Modem manager restarts invalidate all tracked state.
+    name_owner_changed: MessageStream,
+    // InterfacesAdded/InterfacesRemoved on the modem manager.
+    object_manager: MessageStream,
+    // CallAdded/CallDeleted on any modem.
+    voice: MessageStream,
+}
+
+impl ModemSignals {
+    async fn subscribe(system_dbus_conn: &ZbusConnection) -> Result<Self, AnyError> {
+        let name_owner_rule = MatchRule::builder()
+            .msg_type(MessageType::Signal)
+            .sender("org.freedesktop.DBus")?
+            .interface("org.freedesktop.DBus")?
+            .member("NameOwnerChanged")?
+            .add_arg(MM_DEST)?
+            .build();
+        let object_manager_rule = MatchRule::builder()
+            .msg_type(MessageType::Signal)
+            .sender(MM_DEST)?
+            .path(MM_PATH)?
+            .interface(OBJECT_MANAGER_INTERFACE)?
+            .build();
+        let voice_rule = MatchRule::builder()
+            .msg_type(MessageType::Signal)
+            .sender(MM_DEST)?
+            .interface(MM_VOICE_INTERFACE)?
+            .build();
+        Ok(Self {
+            name_owner_changed: MessageStream::for_match_rule(
+                name_owner_rule,
+                system_dbus_conn,
+                None,
+            )
+            .await?,
+            object_manager: MessageStream::for_match_rule(
+                object_manager_rule,
+                system_dbus_conn,
+                None,
+            )
+            .await?,
+            voice: MessageStream::for_match_rule(voice_rule, system_dbus_conn, None).await?,
+        })
+    }
+
+    /// Streams in the order their signals should be processed.
+    /// Modems are added and removed before their calls get updated.
+    fn streams_mut(&mut self) -> [&mut MessageStream; 3] {
+        [
+            &mut self.name_owner_changed,
+            &mut self.object_manager,
+            &mut self.voice,
+        ]
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum SignalEvent {
+    ModemManagerRestarted,
+    VoiceModemAdded(String),
+    VoiceModemRemoved(String),
+    CallAdded { modem: String, call: String },
+    CallDeleted { modem: String, call: String },
+}
+
+fn parse_signal(msg: Arc<zbus::Message>) -> Result<Option<SignalEvent>, AnyError> {
+    let header = msg.header()?;
+    let member = header.member()?.map(|m| m.to_string()).unwrap_or_default();
+    let path = header.path()?.map(|p| p.to_string()).unwrap_or_default();
+    let event = match member.as_str() {
+        "NameOwnerChanged" => Some(SignalEvent::ModemManagerRestarted),
+        "InterfacesAdded" => {
+            let (object, ifaces): (
+                zvariant::OwnedObjectPath,
+                HashMap<String, HashMap<String, zvariant::OwnedValue>>,
+            ) = msg.body()?;
+            ifaces
+                .contains_key(MM_VOICE_INTERFACE)
+                .then(|| SignalEvent::VoiceModemAdded(object.to_string()))
+        }
+        "InterfacesRemoved" => {
+            let (object, ifaces): (zvariant::OwnedObjectPath, Vec<String>) = msg.body()?;
+            ifaces
+                .iter()
+                .any(|iface| iface == MM_VOICE_INTERFACE)
+                .then(|| SignalEvent::VoiceModemRemoved(object.to_string()))
+        }
+        "CallAdded" => {
+            let call: zvariant::OwnedObjectPath = msg.body()?;
+            Some(SignalEvent::CallAdded {
+                modem: path,
+                call: call.to_string(),
+            })
+        }
+        "CallDeleted" => {
+            let call: zvariant::OwnedObjectPath = msg.body()?;
+            Some(SignalEvent::CallDeleted {
+                modem: path,
+                call: call.to_string(),
+            })
+        }
+        _ => None,
+    };
+    Ok(event)
+}
+
+/// Voice calls per modem object path.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct VoiceCalls {
+    modems: HashMap<String, HashSet<String>>,
+}
+
+impl VoiceCalls {
+    fn set_modem_calls(&mut self, modem: &str, calls: Vec<String>) {
+        self.modems
+            .insert(modem.to_string(), calls.into_iter().collect());
+    }
+
+    /// Apply an event. Returns false if the event can't be applied to
+    /// the tracked state and a rescan is needed.
+    fn apply(&mut self, event: SignalEvent) -> bool {
+        match event {
+            SignalEvent::ModemManagerRestarted => {
+                self.modems.clear();
+                false
+            }
+            SignalEvent::VoiceModemAdded(modem) => {
+                self.modems.entry(modem).or_default();
+                true
+            }
+            SignalEvent::VoiceModemRemoved(modem) => {
+                self.modems.remove(&modem);
+                true
+            }
+            SignalEvent::CallAdded { modem, call } => match self.modems.get_mut(&modem) {
+                Some(calls) => {
+                    calls.insert(call);
+                    true
+                }
+                // Call on a modem we don't know of.
+                None => false,
+            },
+            SignalEvent::CallDeleted { modem, call } => {
+                if let Some(calls) = self.modems.get_mut(&modem) {
+                    calls.remove(&call);
+                }
+                true
+            }
         }
-        let any_device_has_calls = devices_have_calls.iter().any(|has_calls| *has_calls);
-        Some(VarValue::Bool(any_device_has_calls))
+    }
+
+    fn any_present(&self) -> bool {
+        self.modems.values().any(|calls| !calls.is_empty())
     }
 }
 
-async fn fetch_voice_devices(system_dbus_conn: &ZbusConnection) -> Option<Vec<String>> {
+async fn fetch_voice_devices(system_dbus_conn: &ZbusConnection) -> Result<Vec<String>, AnyError> {
     let list_devices_res = system_dbus_conn
         .call_method(
-            Some("org.freedesktop.ModemManager1"),
-            "/org/freedesktop/ModemManager1",
-            Some("org.freedesktop.DBus.ObjectManager"),
+            Some(MM_DEST),
+            MM_PATH,
+            Some(OBJECT_MANAGER_INTERFACE),
             "GetManagedObjects",
             &(),
         )
         .await;
     process_list_devices_result(list_devices_res)
-        .map_err(|e| warn!("Failed to list modem manager voice devices: {:?}", e))
-        .ok()
 }
 
 fn process_list_devices_result(
@@ -77,29 +317,68 @@ fn process_list_devices_result(
         zvariant::ObjectPath,
         HashMap<String, HashMap<String, zvariant::Value>>,
     > = list_calls_msg.body()?;
-    devices.retain(|_, ifaces| ifaces.contains_key("org.freedesktop.ModemManager1.Modem.Voice"));
+    devices.retain(|_, ifaces| ifaces.contains_key(MM_VOICE_INTERFACE));
     Ok(devices.keys().map(|path| path.to_string()).collect())
 }
 
-async fn device_has_calls_present(system_dbus_conn: &ZbusConnection, device_path: &str) -> bool {
+async fn list_calls(
+    system_dbus_conn: &ZbusConnection,
+    device_path: &str,
+) -> Result<Vec<String>, AnyError> {
     let list_calls_res = system_dbus_conn
         .call_method(
-            Some("org.freedesktop.ModemManager1"),
+            Some(MM_DEST),
             device_path,
-            Some("org.freedesktop.ModemManager1.Modem.Voice"),
+            Some(MM_VOICE_INTERFACE),
             "ListCalls",
             &(),
         )
         .await;
-    process_has_calls_result(list_calls_res)
-        .map_err(|e| warn!("Failed to call modem manager ListCalls: {:?}", e))
-        .unwrap_or(false)
+    process_list_calls_result(list_calls_res)
 }
 
-fn process_has_calls_result(
+fn process_list_calls_result(
     list_calls_res: Result<Arc<zbus::Message>, zbus::Error>,
-) -> Result<bool, AnyError> {
+) -> Result<Vec<String>, AnyError> {
     let list_calls_msg = list_calls_res?;
     let calls: Vec<zvariant::ObjectPath> = list_calls_msg.body()?;
-    Ok(!calls.is_empty())
+    Ok(calls.iter().map(|call| call.to_string()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_voice_calls_apply() {
+        let modem = "/org/freedesktop/ModemManager1/Modem/0".to_string();
+        let call = "/org/freedesktop/ModemManager1/Call/1".to_string();
+        let mut calls = VoiceCalls::default();
+        assert!(!calls.any_present());
+
+        // Calls on unknown modems need a rescan.
+        assert!(!calls.apply(SignalEvent::CallAdded {
+            modem: modem.clone(),
+            call: call.clone(),
+        }));
+        assert!(calls.apply(SignalEvent::VoiceModemAdded(modem.clone())));
+        assert!(calls.apply(SignalEvent::CallAdded {
+            modem: modem.clone(),
+            call: call.clone(),
+        }));
+        assert!(calls.any_present());
+        assert!(calls.apply(SignalEvent::CallDeleted {
+            modem: modem.clone(),
+            call: call.clone(),
+        }));
+        assert!(!calls.any_present());
+
+        calls.set_modem_calls(&modem, vec![call]);
+        assert!(calls.apply(SignalEvent::VoiceModemRemoved(modem.clone())));
+        assert!(!calls.any_present());
+
+        calls.set_modem_calls(&modem, vec![]);
+        assert!(!calls.apply(SignalEvent::ModemManagerRestarted));
+        assert_eq!(calls, VoiceCalls::default());
+    }
 }
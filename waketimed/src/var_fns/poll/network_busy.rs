@@ -0,0 +1,163 @@
+use crate::core::vars::{param_optional, param_required, VarValue};
+use crate::var_fns::PollVarFns;
+use anyhow::{anyhow, Error as AnyError};
+use async_trait::async_trait;
+use log::{trace, warn};
+use serde_yaml::Value;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::time::{Duration, Instant};
+
+const NET_DEV_PATH: &str = "/proc/net/dev";
+// Fields of /proc/net/dev after the interface name.
+const NET_DEV_RX_BYTES_FIELD: usize = 0;
+const NET_DEV_TX_BYTES_FIELD: usize = 8;
+
+#[derive(Clone, Debug)]
+pub struct NetworkBusyFns {
+    threshold_bytes_per_sec: u64,
+    // Interfaces to count. Empty means all interfaces except loopback.
+    interfaces: Vec<String>,
+    throughput: ThroughputWindow,
+}
+
+impl NetworkBusyFns {
+    pub fn new(params: &HashMap<String, Value>) -> Result<Self, AnyError> {
+        let threshold_bytes_per_sec = param_required::<u64>(params, "threshold_bytes_per_sec")?;
+        let window = param_required::<u64>(params, "window")?;
+        if window == 0 {
+            return Err(anyhow!("Var parameter 'window' must be greater than 0."));
+        }
+        let interfaces = param_optional::<Vec<String>>(params, "interfaces")?.unwrap_or_default();
+        Ok(Self {
+            threshold_bytes_per_sec,
+            interfaces,
+            throughput: ThroughputWindow::new(Duration::from_millis(window)),
+        })
+    }
+}
+
+#[async_trait]
+impl PollVarFns for NetworkBusyFns {
+    async fn poll(&mut self) -> Option<VarValue> {
+        let net_dev = fs::read_to_string(NET_DEV_PATH)
+            .map_err(|e| warn!("Failed to read '{}': {}", NET_DEV_PATH, e))
+            .ok()?;
+        let total_bytes = total_bytes(&net_dev, &self.interfaces);
+        let rate = self.throughput.add_sample(Instant::now(), total_bytes);
+        trace!("Network throughput: {:?} B/s", rate);
+        let busy = rate
+            .map(|rate| rate >= self.threshold_bytes_per_sec)
+            .unwrap_or(false);
+        Some(VarValue::Bool(busy))
+    }
+}
+
+/// Byte counter samples covering a time window, used to compute
+/// average throughput over the window.
+#[derive(Clone, Debug)]
+struct ThroughputWindow {
+    window: Duration,
+    samples: VecDeque<(Instant, u64)>,
+}
+
+impl ThroughputWindow {
+    fn new(window: Duration) -> Self {
+        Self {
+            window,
+            samples: VecDeque::new(),
+        }
+    }
+
+    /// Add a sample of the byte counter and return average throughput
+    /// in bytes per second, if there are enough samples to compute
+    /// it.
+    fn add_sample(&mut self, at: Instant, bytes: u64) -> Option<u64> {
+        if let Some((_, last_bytes)) = self.samples.back() {
+            if bytes < *last_bytes {
+                // Counters got reset, e.g. an interface went away.
+                self.samples.clear();
+            }
+        }
+        self.samples.push_back((at, bytes));
+        // Keep the newest sample which is at least window old, so that
+        // the samples span the whole window.
+        while self.samples.len() > 2 && at.duration_since(self.samples[1].0) >= self.window {
+            self.samples.pop_front();
+        }
+
+        let (first_at, first_bytes) = self.samples.front()?;
+        let elapsed = at.duration_since(*first_at);
+        if elapsed.is_zero() {
+            return None;
+        }
+        let delta = bytes - first_bytes;
+        Some((delta as u128 * 1000 / elapsed.as_millis().max(1)) as u64)
+    }
+}
+
+fn total_bytes(net_dev: &str, interfaces: &[String]) -> u64 {
+    let mut total: u64 = 0;
+    // First two lines are headers.
+    for line in net_dev.lines().skip(2) {
+        let (name, counters) = match line.split_once(':') {
+            Some(parts) => parts,
+            None => continue,
+        };
+        let name = name.trim();
+        let counted = if interfaces.is_empty() {
+            name != "lo"
+        } else {
+            interfaces.iter().any(|iface| iface == name)
+        };
+        if !counted {
+            continue;
+        }
+        let fields: Vec<u64> = counters
+            .split_whitespace()
+            .map(|field| field.parse::<u64>().unwrap_or(0))
+            .collect();
+        total = total
+            .saturating_add(fields.get(NET_DEV_RX_BYTES_FIELD).copied().unwrap_or(0))
+            .saturating_add(fields.get(NET_DEV_TX_BYTES_FIELD).copied().unwrap_or(0));
+    }
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NET_DEV: &str = "\
+Inter-|   Receive                                                |  Transmit
+ face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed
+    lo:  500000    1000    0    0    0     0          0         0   500000    1000    0    0    0     0       0          0
+ wlan0: 1000000    2000    0    0    0     0          0         0    20000     300    0    0    0     0       0          0
+wwan0:     300       3    0    0    0     0          0         0       40       1    0    0    0     0       0          0
+";
+
+    #[test]
+    fn test_total_bytes() {
+        assert_eq!(total_bytes(NET_DEV, &[]), 1_020_340);
+        assert_eq!(total_bytes(NET_DEV, &["wwan0".to_string()]), 340);
+        assert_eq!(total_bytes(NET_DEV, &["eth0".to_string()]), 0);
+    }
+
+    #[test]
+    fn test_throughput_window() {
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        let mut window = ThroughputWindow::new(Duration::from_secs(10));
+
+        assert_eq!(window.add_sample(at(0), 0), None);
+        assert_eq!(window.add_sample(at(5), 5_000), Some(1_000));
+        assert_eq!(window.add_sample(at(10), 10_000), Some(1_000));
+        // Traffic stops, the average goes down gradually over the
+        // window.
+        assert_eq!(window.add_sample(at(15), 10_000), Some(500));
+        assert_eq!(window.add_sample(at(20), 10_000), Some(0));
+        // Counter reset starts over.
+        assert_eq!(window.add_sample(at(25), 100), None);
+        assert_eq!(window.add_sample(at(26), 2_100), Some(2_000));
+    }
+}
@@ -0,0 +1,219 @@
+use crate::core::vars::VarValue;
+use crate::var_creation_context::VarCreationContext;
+use crate::var_fns::PollVarFns;
+use anyhow::{anyhow, Error as AnyError};
+use async_trait::async_trait;
+use log::{trace, warn};
+use serde_yaml::Value;
+use std::collections::HashMap;
+use zbus::Connection as ZbusConnection;
+
+const NM_DEST: &str = "org.freedesktop.NetworkManager";
+const NM_PATH: &str = "/org/freedesktop/NetworkManager";
+const NM_INTERFACE: &str = "org.freedesktop.NetworkManager";
+
+/// Which NetworkManager property the poll var reports.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NetworkManagerVar {
+    Connectivity,
+    Metered,
+    ConnectionType,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct NetworkState {
+    connectivity: String,
+    metered: bool,
+    connection_type: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct NetworkManagerFns {
+    var: NetworkManagerVar,
+    system_dbus_conn: ZbusConnection,
+}
+
+impl NetworkManagerFns {
+    pub fn new(
+        var: NetworkManagerVar,
+        _params: &HashMap<String, Value>,
+        context: &VarCreationContext,
+    ) -> Result<Self, AnyError> {
+        Ok(Self {
+            var,
+            system_dbus_conn: context.system_dbus_conn()?,
+        })
+    }
+}
+
+#[async_trait]
+impl PollVarFns for NetworkManagerFns {
+    async fn poll(&mut self) -> Option<VarValue> {
+        let state = match fetch_network_state(&self.system_dbus_conn).await {
+            Ok(state) => state?,
+            Err(e) => {
+                warn!("Failed to read network state from NetworkManager: {:#}", e);
+                return None;
+            }
+        };
+        trace!("Network state: {:?}", state);
+        Some(match self.var {
+            NetworkManagerVar::Connectivity => VarValue::String(state.connectivity),
+            NetworkManagerVar::Metered => VarValue::Bool(state.metered),
+            NetworkManagerVar::ConnectionType => VarValue::String(state.connection_type),
+        })
+    }
+}
+
+/// Fetch network state, or None if NetworkManager isn't running.
+async fn fetch_network_state(
+    system_dbus_conn: &ZbusConnection,
+) -> Result<Option<NetworkState>, AnyError> {
+    let props_res = system_dbus_conn
+        .call_method(
+            Some(NM_DEST),
+            NM_PATH,
+            Some("org.freedesktop.DBus.Properties"),
+            "GetAll",
+            &[NM_INTERFACE],
+        )
+        .await;
+    let props: HashMap<String, zvariant::OwnedValue> = match props_res {
+        Ok(msg) => msg.body()?,
+        Err(zbus::Error::MethodError(name, _, _))
+            if name.as_str() == "org.freedesktop.DBus.Error.ServiceUnknown" =>
+        {
+            trace!("NetworkManager is not running.");
+            return Ok(None);
+        }
+        Err(e) => return Err(e.into()),
+    };
+    process_nm_props(&props).map(Some)
+}
+
+fn process_nm_props(
+    props: &HashMap<String, zvariant::OwnedValue>,
+) -> Result<NetworkState, AnyError> {
+    let connectivity = match props.get("Connectivity").map(|v| &**v) {
+        // NMConnectivityState
+        Some(zvariant::Value::U32(connectivity)) => match connectivity {
+            1 => "none",
+            2 => "portal",
+            3 => "limited",
+            4 => "full",
+            _ => "unknown",
+        },
+        _ => {
+            return Err(anyhow!(
+                "NetworkManager Connectivity missing or of wrong type."
+            ))
+        }
+    };
+    let metered = match props.get("Metered").map(|v| &**v) {
+        // NMMetered, 1 is "yes" and 3 is "guess-yes".
+        Some(zvariant::Value::U32(metered)) => *metered == 1 || *metered == 3,
+        _ => return Err(anyhow!("NetworkManager Metered missing or of wrong type.")),
+    };
+    let connection_type = match props.get("PrimaryConnectionType").map(|v| &**v) {
+        Some(zvariant::Value::Str(connection_type)) => {
+            simplify_connection_type(connection_type.as_str())
+        }
+        _ => {
+            return Err(anyhow!(
+                "NetworkManager PrimaryConnectionType missing or of wrong type."
+            ))
+        }
+    };
+    Ok(NetworkState {
+        connectivity: connectivity.to_string(),
+        metered,
+        connection_type,
+    })
+}
+
+/// Translate NetworkManager connection setting names to short names.
+/// Uncommon types are passed through.
+fn simplify_connection_type(connection_type: &str) -> String {
+    match connection_type {
+        "" => "none",
+        "802-11-wireless" => "wifi",
+        "802-3-ethernet" => "ethernet",
+        "gsm" | "cdma" => "wwan",
+        other => other,
+    }
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{block_on, mock_bus_connections};
+
+    struct MockNetworkManager {
+        connectivity: u32,
+        metered: u32,
+        primary_connection_type: String,
+    }
+
+    #[zbus::dbus_interface(name = "org.freedesktop.NetworkManager")]
+    impl MockNetworkManager {
+        #[dbus_interface(property)]
+        fn connectivity(&self) -> u32 {
+            self.connectivity
+        }
+
+        #[dbus_interface(property)]
+        fn metered(&self) -> u32 {
+            self.metered
+        }
+
+        #[dbus_interface(property)]
+        fn primary_connection_type(&self) -> String {
+            self.primary_connection_type.clone()
+        }
+    }
+
+    async fn fetch_from_mock(mock: MockNetworkManager) -> Result<Option<NetworkState>, AnyError> {
+        let (_service_conn, client_conn) = mock_bus_connections(NM_PATH, mock).await?;
+        fetch_network_state(&client_conn).await
+    }
+
+    #[test]
+    fn test_fetch_network_state() -> Result<(), AnyError> {
+        let state = block_on(fetch_from_mock(MockNetworkManager {
+            connectivity: 4,
+            metered: 4,
+            primary_connection_type: "802-11-wireless".to_string(),
+        }))?;
+        assert_eq!(
+            state,
+            Some(NetworkState {
+                connectivity: "full".to_string(),
+                metered: false,
+                connection_type: "wifi".to_string(),
+            })
+        );
+
+        let state = block_on(fetch_from_mock(MockNetworkManager {
+            connectivity: 1,
+            metered: 3,
+            primary_connection_type: "gsm".to_string(),
+        }))?;
+        assert_eq!(
+            state,
+            Some(NetworkState {
+                connectivity: "none".to_string(),
+                metered: true,
+                connection_type: "wwan".to_string(),
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_simplify_connection_type() {
+        assert_eq!(simplify_connection_type(""), "none");
+        assert_eq!(simplify_connection_type("802-3-ethernet"), "ethernet");
+        assert_eq!(simplify_connection_type("bluetooth"), "bluetooth");
+    }
+}
@@ -0,0 +1,307 @@
+use crate::core::vars::VarValue;
+use crate::var_creation_context::VarCreationContext;
+use crate::var_fns::PollVarFns;
+use anyhow::{anyhow, Error as AnyError};
+use async_trait::async_trait;
+use log::{debug, trace};
+use serde_yaml::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use zbus::Connection as ZbusConnection;
+
+const POWER_SUPPLY_DIR: &str = "/sys/class/power_supply";
+const UPOWER_DISPLAY_DEVICE: &str = "/org/freedesktop/UPower/devices/DisplayDevice";
+// UPower device State value for charging.
+const UPOWER_STATE_CHARGING: u32 = 1;
+
+/// Which power supply property the poll var reports.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PowerSupplyVar {
+    BatteryPercent,
+    Charging,
+    OnAcPower,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct PowerState {
+    // None if the device has no battery.
+    battery_percent: Option<i64>,
+    charging: bool,
+    on_ac_power: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct PowerSupplyFns {
+    var: PowerSupplyVar,
+    system_dbus_conn: Option<ZbusConnection>,
+}
+
+impl PowerSupplyFns {
+    pub fn new(
+        var: PowerSupplyVar,
+        _params: &HashMap<String, Value>,
+        context: &VarCreationContext,
+    ) -> Result<Self, AnyError> {
+        Ok(Self {
+            var,
+            // UPower is preferred, but sysfs works without D-Bus too.
+            system_dbus_conn: context.system_dbus_conn.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl PollVarFns for PowerSupplyFns {
+    async fn poll(&mut self) -> Option<VarValue> {
+        let mut state = None;
+        if let Some(system_dbus_conn) = self.system_dbus_conn.as_ref() {
+            state = fetch_upower_state(system_dbus_conn)
+                .await
+                .map_err(|e| {
+                    debug!(
+                        "Failed to read power state from UPower, falling back to sysfs: {:#}",
+                        e
+                    )
+                })
+                .ok();
+        }
+        let state = state.unwrap_or_else(|| read_sysfs_state(Path::new(POWER_SUPPLY_DIR)));
+        trace!("Power state: {:?}", state);
+        match self.var {
+            PowerSupplyVar::BatteryPercent => state.battery_percent.map(VarValue::Int),
+            PowerSupplyVar::Charging => Some(VarValue::Bool(state.charging)),
+            PowerSupplyVar::OnAcPower => Some(VarValue::Bool(state.on_ac_power)),
+        }
+    }
+}
+
+async fn fetch_upower_state(system_dbus_conn: &ZbusConnection) -> Result<PowerState, AnyError> {
+    let on_battery_msg = system_dbus_conn
+        .call_method(
+            Some("org.freedesktop.UPower"),
+            "/org/freedesktop/UPower",
+            Some("org.freedesktop.DBus.Properties"),
+            "Get",
+            &["org.freedesktop.UPower", "OnBattery"],
+        )
+        .await?;
+    let on_battery = match on_battery_msg.body::<zvariant::Value>()? {
+        zvariant::Value::Bool(on_battery) => on_battery,
+        _ => return Err(anyhow!("Wrong data type of UPower OnBattery.")),
+    };
+
+    let device_msg = system_dbus_conn
+        .call_method(
+            Some("org.freedesktop.UPower"),
+            UPOWER_DISPLAY_DEVICE,
+            Some("org.freedesktop.DBus.Properties"),
+            "GetAll",
+            &["org.freedesktop.UPower.Device"],
+        )
+        .await?;
+    let device_props: HashMap<String, zvariant::OwnedValue> = device_msg.body()?;
+    process_upower_props(on_battery, &device_props)
+}
+
+fn process_upower_props(
+    on_battery: bool,
+    device_props: &HashMap<String, zvariant::OwnedValue>,
+) -> Result<PowerState, AnyError> {
+    let is_present = match device_props.get("IsPresent").map(|v| &**v) {
+        Some(zvariant::Value::Bool(is_present)) => *is_present,
+        _ => return Err(anyhow!("UPower device IsPresent missing or of wrong type.")),
+    };
+    if !is_present {
+        return Ok(PowerState {
+            battery_percent: None,
+            charging: false,
+            on_ac_power: !on_battery,
+        });
+    }
+    let percentage = match device_props.get("Percentage").map(|v| &**v) {
+        Some(zvariant::Value::F64(percentage)) => *percentage,
+        _ => {
+            return Err(anyhow!(
+                "UPower device Percentage missing or of wrong type."
+            ))
+        }
+    };
+    let state = match device_props.get("State").map(|v| &**v) {
+        Some(zvariant::Value::U32(state)) => *state,
+        _ => return Err(anyhow!("UPower device State missing or of wrong type.")),
+    };
+    Ok(PowerState {
+        battery_percent: Some(percentage.round() as i64),
+        charging: state == UPOWER_STATE_CHARGING,
+        on_ac_power: !on_battery,
+    })
+}
+
+fn read_sysfs_state(power_supply_dir: &Path) -> PowerState {
+    let mut capacities = Vec::new();
+    let mut charging = false;
+    let mut discharging = false;
+    let mut external_supplies = 0;
+    let mut external_online = false;
+
+    for supply_dir in sysfs_supply_dirs(power_supply_dir) {
+        let supply_type = read_attr(&supply_dir, "type").unwrap_or_default();
+        if supply_type == "Battery" {
+            // Batteries of peripherals like mice report scope Device.
+            if read_attr(&supply_dir, "scope").as_deref() == Some("Device") {
+                continue;
+            }
+            if let Some(capacity) =
+                read_attr(&supply_dir, "capacity").and_then(|c| c.parse::<i64>().ok())
+            {
+                capacities.push(capacity);
+            }
+            match read_attr(&supply_dir, "status").as_deref() {
+                Some("Charging") => charging = true,
+                Some("Discharging") => discharging = true,
+                _ => {}
+            }
+        } else {
+            external_supplies += 1;
+            if read_attr(&supply_dir, "online").as_deref() == Some("1") {
+                external_online = true;
+            }
+        }
+    }
+
+    let battery_percent = if capacities.is_empty() {
+        None
+    } else {
+        Some(capacities.iter().sum::<i64>() / capacities.len() as i64)
+    };
+    let on_ac_power = if external_supplies > 0 {
+        external_online
+    } else {
+        // Without any external supply reported, guess from the
+        // battery. Devices without a battery run on mains.
+        !discharging
+    };
+    PowerState {
+        battery_percent,
+        charging,
+        on_ac_power,
+    }
+}
+
+fn sysfs_supply_dirs(power_supply_dir: &Path) -> Vec<PathBuf> {
+    match fs::read_dir(power_supply_dir) {
+        Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+        Err(e) => {
+            debug!("Failed to read '{}': {}", power_supply_dir.display(), e);
+            Vec::new()
+        }
+    }
+}
+
+fn read_attr(supply_dir: &Path, attr: &str) -> Option<String> {
+    fs::read_to_string(supply_dir.join(attr))
+        .ok()
+        .map(|value| value.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::temp_dir;
+
+    fn write_supply(dir: &Path, name: &str, attrs: &[(&str, &str)]) {
+        let supply_dir = dir.join(name);
+        fs::create_dir_all(&supply_dir).expect("Failed to create supply dir.");
+        for (attr, value) in attrs.iter() {
+            fs::write(supply_dir.join(attr), format!("{value}\n")).expect("Failed to write attr.");
+        }
+    }
+
+    #[test]
+    fn test_read_sysfs_state() {
+        let dir = temp_dir("power_supply_sysfs");
+        assert_eq!(
+            read_sysfs_state(&dir),
+            PowerState {
+                battery_percent: None,
+                charging: false,
+                on_ac_power: true,
+            }
+        );
+
+        write_supply(
+            &dir,
+            "battery",
+            &[
+                ("type", "Battery"),
+                ("capacity", "42"),
+                ("status", "Discharging"),
+            ],
+        );
+        write_supply(
+            &dir,
+            "hidpp_battery_0",
+            &[
+                ("type", "Battery"),
+                ("scope", "Device"),
+                ("capacity", "100"),
+                ("status", "Charging"),
+            ],
+        );
+        assert_eq!(
+            read_sysfs_state(&dir),
+            PowerState {
+                battery_percent: Some(42),
+                charging: false,
+                on_ac_power: false,
+            }
+        );
+
+        write_supply(&dir, "usb", &[("type", "USB"), ("online", "0")]);
+        write_supply(&dir, "ac", &[("type", "Mains"), ("online", "1")]);
+        write_supply(&dir, "battery", &[("status", "Charging")]);
+        assert_eq!(
+            read_sysfs_state(&dir),
+            PowerState {
+                battery_percent: Some(42),
+                charging: true,
+                on_ac_power: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_process_upower_props() -> Result<(), AnyError> {
+        let mut props = HashMap::from([
+            ("IsPresent".to_string(), zvariant::Value::Bool(true).into()),
+            ("Percentage".to_string(), zvariant::Value::F64(79.6).into()),
+            (
+                "State".to_string(),
+                zvariant::Value::U32(UPOWER_STATE_CHARGING).into(),
+            ),
+        ]);
+        assert_eq!(
+            process_upower_props(false, &props)?,
+            PowerState {
+                battery_percent: Some(80),
+                charging: true,
+                on_ac_power: true,
+            }
+        );
+
+        props.insert("IsPresent".to_string(), zvariant::Value::Bool(false).into());
+        assert_eq!(
+            process_upower_props(true, &props)?,
+            PowerState {
+                battery_percent: None,
+                charging: false,
+                on_ac_power: false,
+            }
+        );
+
+        props.remove("IsPresent");
+        assert!(process_upower_props(true, &props).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,211 @@
+use crate::core::vars::{param_optional, VarValue};
+use crate::systemd;
+use crate::var_creation_context::VarCreationContext;
+use crate::var_fns::PollVarFns;
+use anyhow::{anyhow, Context, Error as AnyError};
+use async_trait::async_trait;
+use log::{trace, warn};
+use regex::Regex;
+use serde_yaml::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use zbus::Connection as ZbusConnection;
+
+const PROC_DIR: &str = "/proc";
+
+#[derive(Clone, Debug)]
+pub struct ProcessRunningFns {
+    check: ProcessCheck,
+}
+
+#[derive(Clone, Debug)]
+enum ProcessCheck {
+    Processes(ProcessMatcher),
+    SystemdUnit(ZbusConnection, String),
+}
+
+/// Criteria a process must all meet to match.
+#[derive(Clone, Debug, Default)]
+struct ProcessMatcher {
+    name: Option<String>,
+    cmdline_regex: Option<Regex>,
+    cgroup_regex: Option<Regex>,
+}
+
+impl ProcessRunningFns {
+    pub fn new(
+        params: &HashMap<String, Value>,
+        context: &VarCreationContext,
+    ) -> Result<Self, AnyError> {
+        let matcher = ProcessMatcher {
+            name: param_optional::<String>(params, "name")?,
+            cmdline_regex: regex_param(params, "cmdline_regex")?,
+            cgroup_regex: regex_param(params, "cgroup_regex")?,
+        };
+        let systemd_unit = param_optional::<String>(params, "systemd_unit")?;
+        let check = match (matcher.is_empty(), systemd_unit) {
+            (false, None) => ProcessCheck::Processes(matcher),
+            (true, Some(unit)) => ProcessCheck::SystemdUnit(context.system_dbus_conn()?, unit),
+            (true, None) => {
+                return Err(anyhow!(
+                    "One of var parameters 'name', 'cmdline_regex', 'cgroup_regex' or 'systemd_unit' is required."
+                ))
+            }
+            (false, Some(_)) => {
+                return Err(anyhow!(
+                    "Var parameter 'systemd_unit' can't be combined with process matching parameters."
+                ))
+            }
+        };
+        Ok(Self { check })
+    }
+}
+
+#[async_trait]
+impl PollVarFns for ProcessRunningFns {
+    async fn poll(&mut self) -> Option<VarValue> {
+        match &self.check {
+            ProcessCheck::Processes(matcher) => Some(VarValue::Bool(any_process_matches(
+                Path::new(PROC_DIR),
+                matcher,
+            ))),
+            ProcessCheck::SystemdUnit(system_dbus_conn, unit) => {
+                let state = systemd::unit_active_state(system_dbus_conn, unit)
+                    .await
+                    .map_err(|e| warn!("Failed to get state of unit '{}': {:#}", unit, e))
+                    .ok()?;
+                trace!("Unit '{}' state: {}", unit, state);
+                Some(VarValue::Bool(systemd::is_active_state_busy(&state)))
+            }
+        }
+    }
+}
+
+impl ProcessMatcher {
+    fn is_empty(&self) -> bool {
+        self.name.is_none() && self.cmdline_regex.is_none() && self.cgroup_regex.is_none()
+    }
+
+    fn matches(&self, pid_dir: &Path) -> bool {
+        if let Some(name) = self.name.as_ref() {
+            if read_proc_file(pid_dir, "comm").trim_end() != name {
+                return false;
+            }
+        }
+        if let Some(re) = self.cmdline_regex.as_ref() {
+            // Arguments are separated by NUL bytes.
+            let cmdline = read_proc_file(pid_dir, "cmdline")
+                .trim_end_matches('\0')
+                .replace('\0', " ");
+            if !re.is_match(&cmdline) {
+                return false;
+            }
+        }
+        if let Some(re) = self.cgroup_regex.as_ref() {
+            let cgroup = read_proc_file(pid_dir, "cgroup");
+            if !cgroup.lines().any(|line| re.is_match(line)) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+fn regex_param(params: &HashMap<String, Value>, key: &str) -> Result<Option<Regex>, AnyError> {
+    param_optional::<String>(params, key)?
+        .map(|re| {
+            Regex::new(&re).with_context(|| format!("Var parameter '{key}' is not a valid regex"))
+        })
+        .transpose()
+}
+
+fn any_process_matches(proc_dir: &Path, matcher: &ProcessMatcher) -> bool {
+    let pid_dirs: Vec<PathBuf> = match fs::read_dir(proc_dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .filter(|e| {
+                let name = e.file_name();
+                let name = name.to_string_lossy();
+                !name.is_empty() && name.chars().all(|c| c.is_ascii_digit())
+            })
+            .map(|e| e.path())
+            .collect(),
+        Err(e) => {
+            warn!("Failed to read '{}': {}", proc_dir.display(), e);
+            return false;
+        }
+    };
+    // Processes may exit while we scan, unreadable files just don't
+    // match.
+    pid_dirs.iter().any(|pid_dir| matcher.matches(pid_dir))
+}
+
+fn read_proc_file(pid_dir: &Path, file: &str) -> String {
+    fs::read(pid_dir.join(file))
+        .map(|bytes| String::from_utf8_lossy(&bytes).to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::temp_dir;
+
+    fn write_process(proc_dir: &Path, pid: &str, comm: &str, cmdline: &str, cgroup: &str) {
+        let pid_dir = proc_dir.join(pid);
+        fs::create_dir_all(&pid_dir).unwrap();
+        fs::write(pid_dir.join("comm"), format!("{comm}\n")).unwrap();
+        fs::write(pid_dir.join("cmdline"), cmdline).unwrap();
+        fs::write(pid_dir.join("cgroup"), cgroup).unwrap();
+    }
+
+    #[test]
+    fn test_any_process_matches() {
+        let dir = temp_dir("process_running");
+        write_process(
+            &dir,
+            "100",
+            "flatpak",
+            "/usr/bin/flatpak\0update\0-y\0",
+            "0::/user.slice/user-1000.slice/session-2.scope\n",
+        );
+        write_process(
+            &dir,
+            "200",
+            "restic",
+            "restic\0backup\0/home\0",
+            "0::/system.slice/backup.service\n",
+        );
+        // Not a process dir.
+        fs::create_dir_all(dir.join("sys")).unwrap();
+
+        let matcher =
+            |name: Option<&str>, cmdline: Option<&str>, cgroup: Option<&str>| ProcessMatcher {
+                name: name.map(|n| n.to_string()),
+                cmdline_regex: cmdline.map(|re| Regex::new(re).unwrap()),
+                cgroup_regex: cgroup.map(|re| Regex::new(re).unwrap()),
+            };
+        assert!(any_process_matches(
+            &dir,
+            &matcher(Some("flatpak"), None, None)
+        ));
+        assert!(!any_process_matches(
+            &dir,
+            &matcher(Some("flat"), None, None)
+        ));
+        assert!(any_process_matches(
+            &dir,
+            &matcher(None, Some("^/usr/bin/flatpak update"), None)
+        ));
+        assert!(any_process_matches(
+            &dir,
+            &matcher(None, None, Some("/backup\\.service$"))
+        ));
+        // All criteria must match the same process.
+        assert!(!any_process_matches(
+            &dir,
+            &matcher(Some("flatpak"), None, Some("backup"))
+        ));
+    }
+}
@@ -0,0 +1,182 @@
+use crate::core::vars::{param_optional, VarValue};
+use crate::var_creation_context::VarCreationContext;
+use crate::var_fns::PollVarFns;
+use anyhow::{anyhow, Error as AnyError};
+use async_trait::async_trait;
+use log::{trace, warn};
+use serde_yaml::Value;
+use std::collections::HashMap;
+use std::fs;
+use zbus::Connection as ZbusConnection;
+
+const PROC_NET_TCP_PATHS: [&str; 2] = ["/proc/net/tcp", "/proc/net/tcp6"];
+// Connection state value in /proc/net/tcp.
+const TCP_ESTABLISHED: &str = "01";
+
+#[derive(Clone, Debug)]
+pub struct RemoteSessionFns {
+    system_dbus_conn: Option<ZbusConnection>,
+    // Local TCP ports whose established connections count as remote
+    // sessions, e.g. for SSH servers which don't register logind
+    // sessions.
+    tcp_ports: Vec<u16>,
+}
+
+impl RemoteSessionFns {
+    pub fn new(
+        params: &HashMap<String, Value>,
+        context: &VarCreationContext,
+    ) -> Result<Self, AnyError> {
+        let tcp_ports = param_optional::<Vec<u16>>(params, "tcp_ports")?.unwrap_or_default();
+        let system_dbus_conn = if tcp_ports.is_empty() {
+            Some(context.system_dbus_conn()?)
+        } else {
+            // Counting TCP connections works without D-Bus.
+            context.system_dbus_conn.clone()
+        };
+        Ok(Self {
+            system_dbus_conn,
+            tcp_ports,
+        })
+    }
+}
+
+#[async_trait]
+impl PollVarFns for RemoteSessionFns {
+    async fn poll(&mut self) -> Option<VarValue> {
+        let mut logind_result = None;
+        if let Some(system_dbus_conn) = self.system_dbus_conn.as_ref() {
+            match any_remote_session(system_dbus_conn).await {
+                Ok(true) => return Some(VarValue::Bool(true)),
+                Ok(false) => logind_result = Some(false),
+                Err(e) => warn!("Failed to check for remote login sessions: {:#}", e),
+            }
+        }
+        if !self.tcp_ports.is_empty() {
+            let connections: usize = PROC_NET_TCP_PATHS
+                .iter()
+                .map(|path| {
+                    let content = fs::read_to_string(path).unwrap_or_default();
+                    count_established_connections(&content, &self.tcp_ports)
+                })
+                .sum();
+            trace!(
+                "Established connections to ports {:?}: {}",
+                &self.tcp_ports,
+                connections
+            );
+            return Some(VarValue::Bool(connections > 0));
+        }
+        logind_result.map(VarValue::Bool)
+    }
+}
+
+async fn any_remote_session(system_dbus_conn: &ZbusConnection) -> Result<bool, AnyError> {
+    let list_sessions_msg = system_dbus_conn
+        .call_method(
+            Some("org.freedesktop.login1"),
+            "/org/freedesktop/login1",
+            Some("org.freedesktop.login1.Manager"),
+            "ListSessions",
+            &(),
+        )
+        .await?;
+    // (session id, uid, user name, seat id, session object path)
+    let sessions: Vec<(String, u32, String, String, zvariant::OwnedObjectPath)> =
+        list_sessions_msg.body()?;
+    for (session_id, _, _, _, session_path) in sessions.iter() {
+        let remote = session_bool_property(system_dbus_conn, session_path.as_str(), "Remote");
+        let state = session_state(system_dbus_conn, session_path.as_str());
+        match (remote.await, state.await) {
+            (Ok(true), Ok(state)) if state != "closing" => {
+                trace!("Session '{}' is an open remote session.", session_id);
+                return Ok(true);
+            }
+            (Ok(_), Ok(_)) => {}
+            (Err(e), _) | (_, Err(e)) => warn!(
+                "Failed to get properties of session '{}': {:#}",
+                session_id, e
+            ),
+        }
+    }
+    Ok(false)
+}
+
+async fn session_property(
+    system_dbus_conn: &ZbusConnection,
+    session_path: &str,
+    property: &str,
+) -> Result<zvariant::OwnedValue, AnyError> {
+    let msg = system_dbus_conn
+        .call_method(
+            Some("org.freedesktop.login1"),
+            session_path,
+            Some("org.freedesktop.DBus.Properties"),
+            "Get",
+            &["org.freedesktop.login1.Session", property],
+        )
+        .await?;
+    Ok(msg.body()?)
+}
+
+async fn session_bool_property(
+    system_dbus_conn: &ZbusConnection,
+    session_path: &str,
+    property: &str,
+) -> Result<bool, AnyError> {
+    match &*session_property(system_dbus_conn, session_path, property).await? {
+        zvariant::Value::Bool(value) => Ok(*value),
+        _ => Err(anyhow!("Wrong data type of session property {}.", property)),
+    }
+}
+
+async fn session_state(
+    system_dbus_conn: &ZbusConnection,
+    session_path: &str,
+) -> Result<String, AnyError> {
+    match &*session_property(system_dbus_conn, session_path, "State").await? {
+        zvariant::Value::Str(value) => Ok(value.to_string()),
+        _ => Err(anyhow!("Wrong data type of session property State.")),
+    }
+}
+
+/// Count established TCP connections whose local port is among
+/// `ports`, from the content of `/proc/net/tcp` or `/proc/net/tcp6`.
+fn count_established_connections(proc_net_tcp: &str, ports: &[u16]) -> usize {
+    proc_net_tcp
+        .lines()
+        .skip(1)
+        .filter(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            // fields[1] is local address as ADDR:PORT in hex,
+            // fields[3] is connection state.
+            let local_port = fields
+                .get(1)
+                .and_then(|addr| addr.rsplit_once(':'))
+                .and_then(|(_, port)| u16::from_str_radix(port, 16).ok());
+            let state = fields.get(3).copied();
+            match (local_port, state) {
+                (Some(port), Some(state)) => state == TCP_ESTABLISHED && ports.contains(&port),
+                _ => false,
+            }
+        })
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_established_connections() {
+        let proc_net_tcp = "\
+  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 00000000:0016 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 1234 1 0000000000000000 100 0 0 10 0
+   1: 0A00000F:0016 0A000001:D4F2 01 00000000:00000000 02:00098C1A 00000000     0        0 5678 4 0000000000000000 20 4 29 10 -1
+   2: 0A00000F:9C40 5DB8D822:01BB 01 00000000:00000000 00:00000000 00000000  1000        0 9012 1 0000000000000000 20 4 30 10 -1
+";
+        assert_eq!(count_established_connections(proc_net_tcp, &[22]), 1);
+        assert_eq!(count_established_connections(proc_net_tcp, &[2222]), 0);
+        assert_eq!(count_established_connections(proc_net_tcp, &[]), 0);
+    }
+}
@@ -0,0 +1,170 @@
+use crate::core::vars::{param_optional, VarValue};
+use crate::var_fns::PollVarFns;
+use anyhow::{anyhow, Error as AnyError};
+use async_trait::async_trait;
+use log::{debug, trace};
+use serde_yaml::Value;
+use std::collections::HashMap;
+use std::fs;
+
+const PROC_LOADAVG_PATH: &str = "/proc/loadavg";
+const PROC_STAT_PATH: &str = "/proc/stat";
+
+/// Reports the system load average over the last 1, 5 or 15 minutes.
+#[derive(Clone, Debug)]
+pub struct LoadAverageFns {
+    // Index of the field in /proc/loadavg.
+    field_idx: usize,
+}
+
+impl LoadAverageFns {
+    pub fn new(params: &HashMap<String, Value>) -> Result<Self, AnyError> {
+        let minutes = param_optional::<u8>(params, "minutes")?.unwrap_or(1);
+        let field_idx = match minutes {
+            1 => 0,
+            5 => 1,
+            15 => 2,
+            _ => {
+                return Err(anyhow!(
+                    "Var parameter 'minutes' must be one of 1, 5 or 15."
+                ))
+            }
+        };
+        Ok(Self { field_idx })
+    }
+}
+
+#[async_trait]
+impl PollVarFns for LoadAverageFns {
+    async fn poll(&mut self) -> Option<VarValue> {
+        let content = fs::read_to_string(PROC_LOADAVG_PATH)
+            .map_err(|e| debug!("Failed to read '{}': {}", PROC_LOADAVG_PATH, e))
+            .ok()?;
+        parse_load_average(&content, self.field_idx).map(VarValue::Float)
+    }
+}
+
+/// Reports the percentage of CPU time spent not idle since the
+/// previous poll, computed from `/proc/stat` deltas.
+#[derive(Clone, Debug)]
+pub struct CpuUtilizationFns {
+    last_times: Option<CpuTimes>,
+}
+
+impl CpuUtilizationFns {
+    pub fn new(_params: &HashMap<String, Value>) -> Result<Self, AnyError> {
+        Ok(Self {
+            last_times: read_cpu_times(),
+        })
+    }
+}
+
+#[async_trait]
+impl PollVarFns for CpuUtilizationFns {
+    async fn poll(&mut self) -> Option<VarValue> {
+        let times = read_cpu_times()?;
+        let utilization = self
+            .last_times
+            .as_ref()
+            .and_then(|last| utilization_percent(last, &times));
+        trace!("CPU utilization: {:?}", utilization);
+        self.last_times = Some(times);
+        utilization.map(VarValue::Float)
+    }
+}
+
+/// Cumulative CPU time counters of all CPUs, in clock ticks.
+#[derive(Clone, Debug, PartialEq)]
+struct CpuTimes {
+    busy: u64,
+    idle: u64,
+}
+
+fn parse_load_average(content: &str, field_idx: usize) -> Option<f64> {
+    content.split_whitespace().nth(field_idx)?.parse().ok()
+}
+
+fn read_cpu_times() -> Option<CpuTimes> {
+    match fs::read_to_string(PROC_STAT_PATH) {
+        Ok(content) => parse_cpu_times(&content),
+        Err(e) => {
+            debug!("Failed to read '{}': {}", PROC_STAT_PATH, e);
+            None
+        }
+    }
+}
+
+fn parse_cpu_times(proc_stat: &str) -> Option<CpuTimes> {
+    // cpu  user nice system idle iowait irq softirq steal guest guest_nice
+    let line = proc_stat
+        .lines()
+        .find(|line| line.split_whitespace().next() == Some("cpu"))?;
+    let fields: Vec<u64> = line
+        .split_whitespace()
+        .skip(1)
+        .map(|field| field.parse().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    if fields.len() < 4 {
+        return None;
+    }
+    let idle = fields[3] + fields.get(4).copied().unwrap_or(0);
+    // Guest times are already included in user and nice.
+    let total: u64 = fields.iter().take(8).sum();
+    Some(CpuTimes {
+        busy: total - idle,
+        idle,
+    })
+}
+
+/// Percentage of busy CPU time between two samples, rounded to one
+/// decimal place so that the variable doesn't change on noise.
+fn utilization_percent(last: &CpuTimes, current: &CpuTimes) -> Option<f64> {
+    let busy = current.busy.checked_sub(last.busy)?;
+    let idle = current.idle.checked_sub(last.idle)?;
+    let total = busy + idle;
+    if total == 0 {
+        return None;
+    }
+    let percent = busy as f64 * 100.0 / total as f64;
+    Some((percent * 10.0).round() / 10.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_load_average() {
+        let content = "0.52 1.25 2.00 2/713 12345\n";
+        assert_eq!(parse_load_average(content, 0), Some(0.52));
+        assert_eq!(parse_load_average(content, 2), Some(2.0));
+        assert_eq!(parse_load_average("", 0), None);
+    }
+
+    #[test]
+    fn test_cpu_utilization() {
+        let stat_before = "\
+cpu  1000 0 500 8000 500 0 0 0 0 0
+cpu0 500 0 250 4000 250 0 0 0 0 0
+intr 12345
+";
+        let stat_after = "\
+cpu  1600 0 700 8600 600 0 0 0 0 0
+cpu0 800 0 350 4300 300 0 0 0 0 0
+intr 12399
+";
+        let before = parse_cpu_times(stat_before).unwrap();
+        assert_eq!(
+            before,
+            CpuTimes {
+                busy: 1500,
+                idle: 8500
+            }
+        );
+        let after = parse_cpu_times(stat_after).unwrap();
+        // 800 busy ticks out of 1500.
+        assert_eq!(utilization_percent(&before, &after), Some(53.3));
+        assert_eq!(utilization_percent(&after, &after), None);
+        assert_eq!(utilization_percent(&after, &before), None);
+    }
+}
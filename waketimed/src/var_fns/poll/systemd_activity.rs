@@ -0,0 +1,121 @@
+use crate::core::vars::{param_optional, VarValue};
+use crate::systemd;
+use crate::var_creation_context::VarCreationContext;
+use crate::var_fns::PollVarFns;
+use anyhow::{anyhow, Error as AnyError};
+use async_trait::async_trait;
+use log::{trace, warn};
+use serde_yaml::Value;
+use std::collections::HashMap;
+use zbus::Connection as ZbusConnection;
+
+// Jobs which suspending the system itself queues. They must not keep
+// the system awake, otherwise a suspend request would postpone
+// suspend.
+const SLEEP_JOB_UNITS: [&str; 9] = [
+    "sleep.target",
+    "suspend.target",
+    "hibernate.target",
+    "hybrid-sleep.target",
+    "suspend-then-hibernate.target",
+    "systemd-suspend.service",
+    "systemd-hibernate.service",
+    "systemd-hybrid-sleep.service",
+    "systemd-suspend-then-hibernate.service",
+];
+
+#[derive(Clone, Debug)]
+pub struct SystemdActivityFns {
+    system_dbus_conn: ZbusConnection,
+    // Unit names or glob patterns.
+    units: Vec<String>,
+    jobs: bool,
+}
+
+impl SystemdActivityFns {
+    pub fn new(
+        params: &HashMap<String, Value>,
+        context: &VarCreationContext,
+    ) -> Result<Self, AnyError> {
+        let units = param_optional::<Vec<String>>(params, "units")?.unwrap_or_default();
+        let jobs = param_optional::<bool>(params, "jobs")?.unwrap_or(false);
+        if units.is_empty() && !jobs {
+            return Err(anyhow!(
+                "Var parameter 'units' must be non-empty, or parameter 'jobs' must be true."
+            ));
+        }
+        Ok(Self {
+            system_dbus_conn: context.system_dbus_conn()?,
+            units,
+            jobs,
+        })
+    }
+
+    async fn any_unit_busy(&self) -> Result<bool, AnyError> {
+        if self.units.is_empty() {
+            return Ok(false);
+        }
+        let states =
+            systemd::unit_active_states_by_patterns(&self.system_dbus_conn, &self.units).await?;
+        trace!("Unit states: {:?}", states);
+        Ok(states
+            .iter()
+            .any(|(_, state)| systemd::is_active_state_busy(state)))
+    }
+
+    async fn any_job_queued(&self) -> Result<bool, AnyError> {
+        if !self.jobs {
+            return Ok(false);
+        }
+        let job_units = systemd::queued_job_units(&self.system_dbus_conn).await?;
+        trace!("Units with queued jobs: {:?}", job_units);
+        Ok(any_non_sleep_job(&job_units))
+    }
+}
+
+#[async_trait]
+impl PollVarFns for SystemdActivityFns {
+    async fn poll(&mut self) -> Option<VarValue> {
+        let units_busy = self
+            .any_unit_busy()
+            .await
+            .map_err(|e| warn!("Failed to check states of systemd units: {:#}", e))
+            .ok()?;
+        if units_busy {
+            return Some(VarValue::Bool(true));
+        }
+        let job_queued = self
+            .any_job_queued()
+            .await
+            .map_err(|e| warn!("Failed to list systemd jobs: {:#}", e))
+            .ok()?;
+        Some(VarValue::Bool(job_queued))
+    }
+}
+
+fn any_non_sleep_job(job_units: &[String]) -> bool {
+    job_units
+        .iter()
+        .any(|unit| !SLEEP_JOB_UNITS.contains(&unit.as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_any_non_sleep_job() {
+        let units =
+            |names: &[&str]| -> Vec<String> { names.iter().map(|n| n.to_string()).collect() };
+        assert!(!any_non_sleep_job(&units(&[])));
+        assert!(!any_non_sleep_job(&units(&[
+            "suspend.target",
+            "systemd-suspend.service",
+            "sleep.target"
+        ])));
+        assert!(any_non_sleep_job(&units(&[
+            "suspend.target",
+            "fwupd-refresh.service"
+        ])));
+    }
+}
@@ -0,0 +1,75 @@
+use crate::core::vars::VarValue;
+use crate::var_fns::PollVarFns;
+use anyhow::Error as AnyError;
+use async_trait::async_trait;
+use log::{debug, trace};
+use serde_yaml::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const THERMAL_CLASS_DIR: &str = "/sys/class/thermal";
+
+/// Reports the highest temperature of all thermal zones, in degrees
+/// Celsius. Undefined when the device has no thermal zones.
+#[derive(Clone, Debug)]
+pub struct ThermalMaxCelsiusFns {
+    class_dir: PathBuf,
+}
+
+impl ThermalMaxCelsiusFns {
+    pub fn new(_params: &HashMap<String, Value>) -> Result<Self, AnyError> {
+        Ok(Self {
+            class_dir: PathBuf::from(THERMAL_CLASS_DIR),
+        })
+    }
+}
+
+#[async_trait]
+impl PollVarFns for ThermalMaxCelsiusFns {
+    async fn poll(&mut self) -> Option<VarValue> {
+        max_zone_celsius(&self.class_dir).map(VarValue::Float)
+    }
+}
+
+fn max_zone_celsius(class_dir: &Path) -> Option<f64> {
+    let entries = match fs::read_dir(class_dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            debug!("Failed to read '{}': {}", class_dir.display(), e);
+            return None;
+        }
+    };
+    entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_name().to_string_lossy().starts_with("thermal_zone"))
+        // Reading temperature of a disabled zone or a sensor which
+        // is powered down fails, skip such zones.
+        .filter_map(|e| fs::read_to_string(e.path().join("temp")).ok())
+        // Temperature is reported in millidegrees Celsius.
+        .filter_map(|temp| temp.trim().parse::<i64>().ok())
+        .max()
+        .map(|millicelsius| {
+            trace!("Max thermal zone temperature: {} m°C", millicelsius);
+            millicelsius as f64 / 1000.0
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::temp_dir;
+
+    #[test]
+    fn test_max_zone_celsius() {
+        let dir = temp_dir("thermal_max_celsius");
+        assert_eq!(max_zone_celsius(&dir), None);
+        for (zone, temp) in [("thermal_zone0", "41500"), ("thermal_zone1", "63250")] {
+            fs::create_dir_all(dir.join(zone)).unwrap();
+            fs::write(dir.join(zone).join("temp"), format!("{temp}\n")).unwrap();
+        }
+        // Cooling devices don't report temperature.
+        fs::create_dir_all(dir.join("cooling_device0")).unwrap();
+        assert_eq!(max_zone_celsius(&dir), Some(63.25));
+    }
+}
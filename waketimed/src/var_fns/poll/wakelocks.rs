@@ -0,0 +1,201 @@
+use crate::core::vars::{param_optional, VarValue};
+use crate::var_fns::PollVarFns;
+use anyhow::{Context, Error as AnyError};
+use async_trait::async_trait;
+use log::{debug, trace};
+use regex::Regex;
+use serde_yaml::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const WAKE_LOCK_PATH: &str = "/sys/power/wake_lock";
+const WAKEUP_CLASS_DIR: &str = "/sys/class/wakeup";
+const WAKEUP_SOURCES_DEBUGFS_PATH: &str = "/sys/kernel/debug/wakeup_sources";
+
+/// Reports whether any user space wake lock (written into
+/// `/sys/power/wake_lock`) is held.
+#[derive(Clone, Debug)]
+pub struct WakeLockHeldFns {
+    name_filter: Option<Regex>,
+}
+
+impl WakeLockHeldFns {
+    pub fn new(params: &HashMap<String, Value>) -> Result<Self, AnyError> {
+        Ok(Self {
+            name_filter: name_filter_param(params)?,
+        })
+    }
+}
+
+#[async_trait]
+impl PollVarFns for WakeLockHeldFns {
+    async fn poll(&mut self) -> Option<VarValue> {
+        // Missing file means the kernel doesn't support user space
+        // wake locks, so none can be held.
+        let content = fs::read_to_string(WAKE_LOCK_PATH).unwrap_or_default();
+        let held = held_wake_locks(&content);
+        trace!("Held wake locks: {:?}", held);
+        Some(VarValue::Bool(any_name_matches(
+            held.into_iter(),
+            self.name_filter.as_ref(),
+        )))
+    }
+}
+
+/// Reports whether any kernel wakeup source is active.
+#[derive(Clone, Debug)]
+pub struct WakeupSourceActiveFns {
+    name_filter: Option<Regex>,
+}
+
+impl WakeupSourceActiveFns {
+    pub fn new(params: &HashMap<String, Value>) -> Result<Self, AnyError> {
+        Ok(Self {
+            name_filter: name_filter_param(params)?,
+        })
+    }
+}
+
+#[async_trait]
+impl PollVarFns for WakeupSourceActiveFns {
+    async fn poll(&mut self) -> Option<VarValue> {
+        let class_dir = Path::new(WAKEUP_CLASS_DIR);
+        let active = if class_dir.is_dir() {
+            active_wakeup_sources_sysfs(class_dir)
+        } else {
+            match fs::read_to_string(WAKEUP_SOURCES_DEBUGFS_PATH) {
+                Ok(content) => active_wakeup_sources_debugfs(&content),
+                Err(e) => {
+                    debug!("Failed to read '{}': {}", WAKEUP_SOURCES_DEBUGFS_PATH, e);
+                    return None;
+                }
+            }
+        };
+        trace!("Active wakeup sources: {:?}", active);
+        Some(VarValue::Bool(any_name_matches(
+            active.iter().map(|name| name.as_str()),
+            self.name_filter.as_ref(),
+        )))
+    }
+}
+
+fn name_filter_param(params: &HashMap<String, Value>) -> Result<Option<Regex>, AnyError> {
+    param_optional::<String>(params, "name_regex")?
+        .map(|re| Regex::new(&re).context("Var parameter 'name_regex' is not a valid regex"))
+        .transpose()
+}
+
+fn any_name_matches<'a, I>(mut names: I, name_filter: Option<&Regex>) -> bool
+where
+    I: Iterator<Item = &'a str>,
+{
+    names.any(|name| name_filter.map(|re| re.is_match(name)).unwrap_or(true))
+}
+
+fn held_wake_locks(content: &str) -> Vec<&str> {
+    content.split_whitespace().collect()
+}
+
+fn active_wakeup_sources_sysfs(class_dir: &Path) -> Vec<String> {
+    let source_dirs: Vec<PathBuf> = match fs::read_dir(class_dir) {
+        Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+        Err(e) => {
+            debug!("Failed to read '{}': {}", class_dir.display(), e);
+            return Vec::new();
+        }
+    };
+    source_dirs
+        .iter()
+        .filter(|dir| {
+            // Time the source has been continuously active, 0 when
+            // inactive.
+            fs::read_to_string(dir.join("active_time_ms"))
+                .ok()
+                .and_then(|t| t.trim().parse::<u64>().ok())
+                .map(|t| t > 0)
+                .unwrap_or(false)
+        })
+        .filter_map(|dir| fs::read_to_string(dir.join("name")).ok())
+        .map(|name| name.trim().to_string())
+        .collect()
+}
+
+fn active_wakeup_sources_debugfs(content: &str) -> Vec<String> {
+    let mut lines = content.lines();
+    let header: Vec<&str> = match lines.next() {
+        Some(header) => header.split_whitespace().collect(),
+        None => return Vec::new(),
+    };
+    let active_since_idx = match header.iter().position(|col| *col == "active_since") {
+        Some(idx) => idx,
+        None => return Vec::new(),
+    };
+    lines
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let active_since = fields.get(active_since_idx)?.parse::<u64>().ok()?;
+            if active_since > 0 {
+                fields.first().map(|name| name.to_string())
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::temp_dir;
+
+    #[test]
+    fn test_wake_locks() {
+        let held = held_wake_locks("PowerManagerService.Display modem_rx\n");
+        assert_eq!(held, vec!["PowerManagerService.Display", "modem_rx"]);
+        assert!(any_name_matches(held.clone().into_iter(), None));
+        let re = Regex::new("^modem").unwrap();
+        assert!(any_name_matches(held.into_iter(), Some(&re)));
+        let re = Regex::new("^sms").unwrap();
+        assert!(!any_name_matches(
+            held_wake_locks("modem_rx").into_iter(),
+            Some(&re)
+        ));
+        assert!(!any_name_matches(held_wake_locks("\n").into_iter(), None));
+    }
+
+    #[test]
+    fn test_active_wakeup_sources_sysfs() {
+        let dir = temp_dir("wakeup_sources_sysfs");
+        for (source, name, active_time) in [
+            ("wakeup0", "alarmtimer", "0"),
+            ("wakeup1", "modem_rx", "1234"),
+        ] {
+            let source_dir = dir.join(source);
+            fs::create_dir_all(&source_dir).unwrap();
+            fs::write(source_dir.join("name"), format!("{name}\n")).unwrap();
+            fs::write(
+                source_dir.join("active_time_ms"),
+                format!("{active_time}\n"),
+            )
+            .unwrap();
+        }
+        assert_eq!(
+            active_wakeup_sources_sysfs(&dir),
+            vec!["modem_rx".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_active_wakeup_sources_debugfs() {
+        let content = "\
+name\t\tactive_count\tevent_count\twakeup_count\texpire_count\tactive_since\ttotal_time\tmax_time\tlast_change\tprevent_suspend_time
+alarmtimer\t0\t0\t0\t0\t0\t0\t0\t0\t0
+modem_rx\t5\t5\t0\t0\t1500\t3000\t800\t100\t0
+";
+        assert_eq!(
+            active_wakeup_sources_debugfs(content),
+            vec!["modem_rx".to_string()]
+        );
+    }
+}
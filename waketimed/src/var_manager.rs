@@ -1,8 +1,9 @@
 use crate::config::Config;
-use crate::core::vars::{VarDef, VarKind, VarName, VarValue};
+use crate::core::vars::{VarDataType, VarDef, VarKind, VarName, VarValue};
 use crate::files;
 use crate::messages::WorkerMsg;
 use anyhow::{anyhow, Error as AnyError};
+use chrono::{DateTime, Datelike, TimeZone, Timelike};
 use getset::Getters;
 use log::{debug, error, trace};
 use std::collections::{HashMap, HashSet};
@@ -15,8 +16,12 @@ pub struct VarManager {
     worker_send: UnboundedSender<WorkerMsg>,
     #[getset(get = "pub")]
     vars: HashMap<VarName, VarValue>,
+    #[getset(get = "pub")]
     var_defs: HashMap<VarName, VarDef>,
+    #[getset(get = "pub")]
     category_vars: HashMap<VarName, HashSet<VarName>>,
+    // Vars changed since the last take_changed_vars call.
+    changed_vars: HashSet<VarName>,
     waitlist_poll: HashSet<VarName>,
 }
 
@@ -28,6 +33,7 @@ impl VarManager {
             vars: HashMap::new(),
             var_defs: HashMap::new(),
             category_vars: HashMap::new(),
+            changed_vars: HashSet::new(),
             waitlist_poll: HashSet::new(),
         })
     }
@@ -71,13 +77,67 @@ impl VarManager {
                         );
                         false
                     });
-                    Self::set_var(&mut self.vars, var_name.clone(), VarValue::Bool(value));
+                    Self::set_var(
+                        &mut self.vars,
+                        &mut self.changed_vars,
+                        var_name.clone(),
+                        VarValue::Bool(value),
+                    );
                 }
                 _ => {}
             }
         }
     }
 
+    /// Restore var values saved by a previous waketimed process.
+    /// Values of vars which are no longer defined, or whose data
+    /// type changed, are skipped. Category vars get recomputed.
+    pub fn restore_vars(&mut self, vars: &HashMap<VarName, VarValue>) {
+        for (var_name, value) in vars.iter() {
+            match self.var_defs.get(var_name) {
+                Some(var_def)
+                    if var_def.data_type == value.data_type()
+                        && !matches!(var_def.kind, VarKind::CategoryAny(_)) =>
+                {
+                    Self::set_var(
+                        &mut self.vars,
+                        &mut self.changed_vars,
+                        var_name.clone(),
+                        value.clone(),
+                    );
+                }
+                _ => trace!("Not restoring value of var '{}'.", var_name),
+            }
+        }
+    }
+
+    pub fn set_builtin_engine_var(&mut self, builtin_name: &str, value: VarValue) {
+        for (var_name, var_def) in self.var_defs.iter() {
+            if let VarKind::BuiltinEngine(def) = &var_def.kind {
+                if def.builtin_name == builtin_name {
+                    Self::set_var(
+                        &mut self.vars,
+                        &mut self.changed_vars,
+                        var_name.clone(),
+                        value.clone(),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Set builtin engine vars describing local wall-clock time.
+    pub fn update_local_time_vars<Tz: TimeZone>(&mut self, now: &DateTime<Tz>) {
+        for (builtin_name, value) in local_time_values(now) {
+            self.set_builtin_engine_var(builtin_name, value);
+        }
+    }
+
+    /// Names of vars whose values changed since the previous call.
+    pub fn take_changed_vars(&mut self) -> HashSet<VarName> {
+        std::mem::take(&mut self.changed_vars)
+    }
+
     pub fn spawn_poll_var_interval(&mut self) -> Result<(), AnyError> {
         let interval = self.cfg.poll_variable_interval;
         self.worker_send
@@ -85,15 +145,23 @@ impl VarManager {
         Ok(())
     }
 
-    pub fn handle_return_var_poll(&mut self, var_name: VarName, opt_value: Option<VarValue>) {
+    /// Returns true if the value of the variable changed.
+    pub fn handle_return_var_poll(
+        &mut self,
+        var_name: VarName,
+        opt_value: Option<VarValue>,
+    ) -> bool {
         self.waitlist_poll.remove(&var_name);
         if let Some(value) = opt_value {
-            Self::set_var(&mut self.vars, var_name, value);
+            Self::set_var(&mut self.vars, &mut self.changed_vars, var_name, value)
+        } else {
+            false
         }
     }
 
     fn load_var_defs(&mut self) -> Result<(), AnyError> {
         self.var_defs = files::load_var_defs(&self.cfg)?;
+        self.check_builtin_engine_var_defs()?;
         self.category_vars = self.compute_category_vars_map();
         Ok(())
     }
@@ -108,6 +176,30 @@ impl VarManager {
         Ok(())
     }
 
+    fn check_builtin_engine_var_defs(&self) -> Result<(), AnyError> {
+        for var_def in self.var_defs.values() {
+            if let VarKind::BuiltinEngine(def) = &var_def.kind {
+                let data_type =
+                    builtin_engine_var_data_type(&def.builtin_name).ok_or_else(|| {
+                        anyhow!(
+                            "Var '{}' definition specified unknown builtin_name: '{}'.",
+                            var_def.name(),
+                            &def.builtin_name
+                        )
+                    })?;
+                if var_def.data_type != data_type {
+                    return Err(anyhow!(
+                        "Var '{}' must have data_type '{:?}' to use builtin_name '{}'.",
+                        var_def.name(),
+                        data_type,
+                        &def.builtin_name
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
     fn compute_category_vars_map(&self) -> HashMap<VarName, HashSet<VarName>> {
         let mut category_vars = HashMap::new();
         for var_def in self.var_defs.values() {
@@ -132,19 +224,26 @@ impl VarManager {
         category_vars
     }
 
-    fn set_var(vars: &mut HashMap<VarName, VarValue>, name: VarName, value: VarValue) {
+    fn set_var(
+        vars: &mut HashMap<VarName, VarValue>,
+        changed_vars: &mut HashSet<VarName>,
+        name: VarName,
+        value: VarValue,
+    ) -> bool {
         let old_value = vars.get(&name);
-        if old_value != Some(&value) {
+        let changed = old_value != Some(&value);
+        if changed {
             debug!("Variable changed: {} = {}", &name, &value);
+            changed_vars.insert(name.clone());
         }
         vars.insert(name, value);
+        changed
     }
 
     fn is_any_bool_var_true(&self, var_names: &HashSet<VarName>) -> Result<bool, AnyError> {
         let var_bools: Result<Vec<bool>, AnyError> = var_names
             .iter()
             .map(|v| {
-                #[allow(irrefutable_let_patterns)]
                 if let VarValue::Bool(b) = self.get_cloned_or(v, VarValue::Bool(false)) {
                     Ok(b)
                 } else {
@@ -163,10 +262,36 @@ impl VarManager {
     }
 }
 
+fn builtin_engine_var_data_type(builtin_name: &str) -> Option<VarDataType> {
+    match builtin_name {
+        "wake_reason" => Some(VarDataType::String),
+        "local_hour" | "local_minute" | "local_weekday" => Some(VarDataType::Int),
+        "local_date" => Some(VarDataType::String),
+        _ => None,
+    }
+}
+
+fn local_time_values<Tz: TimeZone>(now: &DateTime<Tz>) -> [(&'static str, VarValue); 4] {
+    [
+        ("local_hour", VarValue::Int(now.hour().into())),
+        ("local_minute", VarValue::Int(now.minute().into())),
+        // ISO 8601 numbering, Monday is 1 and Sunday is 7.
+        (
+            "local_weekday",
+            VarValue::Int(now.weekday().number_from_monday().into()),
+        ),
+        (
+            "local_date",
+            VarValue::String(now.date_naive().format("%Y-%m-%d").to_string()),
+        ),
+    ]
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::test_helpers::{run_and_term_without_builtin_defs_config, var_name};
+    use chrono::{FixedOffset, Utc};
     use tokio::sync::mpsc::UnboundedReceiver;
 
     fn create_var_manager(cfg: Config) -> (VarManager, UnboundedReceiver<WorkerMsg>) {
@@ -221,4 +346,91 @@ mod tests {
             &VarValue::Bool(false)
         );
     }
+
+    #[test]
+    fn test_builtin_engine_vars() {
+        let (mut mgr, _worker_recv) =
+            create_var_manager(run_and_term_without_builtin_defs_config());
+        mgr.init().expect("Failed to init VarManager.");
+        let test_wake_reason = var_name("test_wake_reason");
+        assert_eq!(mgr.vars.get(&test_wake_reason), None);
+
+        mgr.set_builtin_engine_var("wake_reason", VarValue::String("modem".to_string()));
+        assert_eq!(
+            mgr.vars.get(&test_wake_reason),
+            Some(&VarValue::String("modem".to_string()))
+        );
+        mgr.set_builtin_engine_var("unrelated", VarValue::Bool(true));
+        assert_eq!(
+            mgr.vars.get(&test_wake_reason),
+            Some(&VarValue::String("modem".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_take_changed_vars() {
+        let (mut mgr, _worker_recv) =
+            create_var_manager(run_and_term_without_builtin_defs_config());
+        mgr.init().expect("Failed to init VarManager.");
+        let test_poll_true = var_name("test_poll_true");
+        assert!(mgr.handle_return_var_poll(test_poll_true.clone(), Some(VarValue::Bool(true))));
+        mgr.update_category_vars();
+        assert_eq!(
+            mgr.take_changed_vars(),
+            [test_poll_true.clone(), var_name("test_category")].into()
+        );
+
+        // Setting the same values again is not a change.
+        mgr.handle_return_var_poll(test_poll_true.clone(), Some(VarValue::Bool(true)));
+        mgr.update_category_vars();
+        assert_eq!(mgr.take_changed_vars(), HashSet::new());
+    }
+
+    #[test]
+    fn test_local_time_values() {
+        let now = FixedOffset::east_opt(2 * 3600)
+            .unwrap()
+            .with_ymd_and_hms(2023, 3, 6, 0, 30, 0)
+            .unwrap();
+        let values: HashMap<&str, VarValue> = local_time_values(&now).into_iter().collect();
+        assert_eq!(values["local_hour"], VarValue::Int(0));
+        assert_eq!(values["local_minute"], VarValue::Int(30));
+        assert_eq!(values["local_weekday"], VarValue::Int(1));
+        assert_eq!(
+            values["local_date"],
+            VarValue::String("2023-03-06".to_string())
+        );
+
+        // The same instant is still Sunday in UTC.
+        let values: HashMap<&str, VarValue> = local_time_values(&now.with_timezone(&Utc))
+            .into_iter()
+            .collect();
+        assert_eq!(values["local_hour"], VarValue::Int(22));
+        assert_eq!(values["local_weekday"], VarValue::Int(7));
+        assert_eq!(
+            values["local_date"],
+            VarValue::String("2023-03-05".to_string())
+        );
+    }
+
+    #[test]
+    fn test_restore_vars() {
+        let (mut mgr, _worker_recv) =
+            create_var_manager(run_and_term_without_builtin_defs_config());
+        mgr.init().expect("Failed to init VarManager.");
+        let saved = HashMap::from([
+            (var_name("test_poll_true"), VarValue::Bool(true)),
+            // category vars are computed, not restored
+            (var_name("test_category"), VarValue::Bool(true)),
+            // wrong data type
+            (var_name("test_wake_reason"), VarValue::Bool(true)),
+            // not defined
+            (var_name("test_undefined"), VarValue::Bool(true)),
+        ]);
+        mgr.restore_vars(&saved);
+        assert_eq!(
+            mgr.vars,
+            HashMap::from([(var_name("test_poll_true"), VarValue::Bool(true))])
+        );
+    }
 }
@@ -0,0 +1,263 @@
+use anyhow::{anyhow, Error as AnyError};
+use log::{debug, trace};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+const PM_WAKEUP_IRQ_PATH: &str = "/sys/power/pm_wakeup_irq";
+const PROC_INTERRUPTS_PATH: &str = "/proc/interrupts";
+const WAKEUP_CLASS_DIR: &str = "/sys/class/wakeup";
+const RTC_ALARM_KEYWORDS: &[&str] = &["rtc", "alarm", "alarmtimer"];
+const MODEM_KEYWORDS: &[&str] = &["modem", "wwan", "ipa", "qmi", "mhi", "ring"];
+const POWER_BUTTON_KEYWORDS: &[&str] = &["pwrkey", "power", "button", "keys", "pon"];
+const USB_KEYWORDS: &[&str] = &[
+    "usb", "typec", "tcpm", "charger", "supply", "vbus", "otg", "dwc3", "xhci", "ehci", "musb",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WakeReason {
+    /// The device has not been woken up since waketimed started.
+    None,
+    /// Any RTC or alarm timer wake-up, waketimed does not arm its own
+    /// wake alarm.
+    RtcAlarm,
+    Modem,
+    PowerButton,
+    Usb,
+    Unknown,
+}
+
+impl WakeReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WakeReason::None => "none",
+            WakeReason::RtcAlarm => "rtc_alarm",
+            WakeReason::Modem => "modem",
+            WakeReason::PowerButton => "power_button",
+            WakeReason::Usb => "usb",
+            WakeReason::Unknown => "unknown",
+        }
+    }
+}
+
+impl FromStr for WakeReason {
+    type Err = AnyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            WakeReason::None,
+            WakeReason::RtcAlarm,
+            WakeReason::Modem,
+            WakeReason::PowerButton,
+            WakeReason::Usb,
+            WakeReason::Unknown,
+        ]
+        .into_iter()
+        .find(|reason| reason.as_str() == s)
+        .ok_or_else(|| anyhow!("Unknown wake reason '{}'.", s))
+    }
+}
+
+impl fmt::Display for WakeReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        f.write_str(self.as_str())
+    }
+}
+
+/// State of wake-up related kernel interfaces captured right before
+/// suspend, so that it can be compared with the state after resume.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WakeupSnapshot {
+    wakeup_counts: HashMap<String, u64>,
+}
+
+pub fn take_snapshot() -> WakeupSnapshot {
+    let snapshot = WakeupSnapshot {
+        wakeup_counts: read_wakeup_counts(),
+    };
+    trace!("Wakeup snapshot: {:?}", &snapshot);
+    snapshot
+}
+
+/// Figure out why the system woke up, based on the snapshot taken
+/// before suspend and the current state of kernel interfaces. Alarm
+/// wake-ups, including `CLOCK_BOOTTIME_ALARM` timers, show up as the
+/// rtc or alarmtimer wakeup sources.
+pub fn classify_resume(before: &WakeupSnapshot) -> WakeReason {
+    if let Some(irq_desc) = read_wakeup_irq_description() {
+        debug!("Wakeup IRQ: {}", &irq_desc);
+        if let Some(reason) = classify_source_name(&irq_desc) {
+            return reason;
+        }
+    }
+
+    let after_counts = read_wakeup_counts();
+    let woken_sources = increased_wakeup_sources(&before.wakeup_counts, &after_counts);
+    debug!(
+        "Wakeup sources with new wakeup events: {:?}",
+        &woken_sources
+    );
+    woken_sources
+        .iter()
+        .find_map(|name| classify_source_name(name))
+        .unwrap_or(WakeReason::Unknown)
+}
+
+fn classify_source_name(name: &str) -> Option<WakeReason> {
+    let lower_name = name.to_lowercase();
+    let tokens: Vec<&str> = lower_name
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        // Device names are often numbered, e.g. "rtc0".
+        .map(|token| token.trim_end_matches(|c: char| c.is_ascii_digit()))
+        .filter(|token| !token.is_empty())
+        .collect();
+    let has_any = |keywords: &[&str]| tokens.iter().any(|token| keywords.contains(token));
+    if has_any(RTC_ALARM_KEYWORDS) {
+        Some(WakeReason::RtcAlarm)
+    } else if has_any(MODEM_KEYWORDS) {
+        Some(WakeReason::Modem)
+    // Checked before the power button, as charger and power supply
+    // names often contain "power" too.
+    } else if has_any(USB_KEYWORDS) {
+        Some(WakeReason::Usb)
+    } else if has_any(POWER_BUTTON_KEYWORDS) {
+        Some(WakeReason::PowerButton)
+    } else {
+        None
+    }
+}
+
+fn increased_wakeup_sources(
+    before: &HashMap<String, u64>,
+    after: &HashMap<String, u64>,
+) -> Vec<String> {
+    let mut names: Vec<String> = after
+        .iter()
+        .filter(|(name, count)| *count > before.get(*name).unwrap_or(&0))
+        .map(|(name, _)| name.clone())
+        .collect();
+    names.sort_unstable();
+    names
+}
+
+fn read_wakeup_irq_description() -> Option<String> {
+    let irq = fs::read_to_string(PM_WAKEUP_IRQ_PATH).ok()?;
+    let interrupts = fs::read_to_string(PROC_INTERRUPTS_PATH).ok()?;
+    irq_description(&interrupts, irq.trim())
+}
+
+// Find description (chip, hw irq, trigger, device name) of the given
+// IRQ in /proc/interrupts content. The per-CPU counter columns are
+// skipped.
+fn irq_description(interrupts: &str, irq: &str) -> Option<String> {
+    let irq_label = format!("{irq}:");
+    interrupts.lines().find_map(|line| {
+        let mut fields = line.split_whitespace();
+        if fields.next() != Some(irq_label.as_str()) {
+            return None;
+        }
+        let desc: Vec<&str> = fields
+            .skip_while(|field| field.chars().all(|c| c.is_ascii_digit()))
+            .collect();
+        Some(desc.join(" "))
+    })
+}
+
+fn read_wakeup_counts() -> HashMap<String, u64> {
+    let mut counts = HashMap::new();
+    let entries = match fs::read_dir(WAKEUP_CLASS_DIR) {
+        Ok(entries) => entries,
+        Err(_) => return counts,
+    };
+    for entry in entries.flatten() {
+        let dir = entry.path();
+        if let (Some(name), Some(count)) = (
+            read_trimmed(dir.join("name")),
+            read_trimmed(dir.join("wakeup_count")).and_then(|c| c.parse::<u64>().ok()),
+        ) {
+            counts.insert(name, count);
+        }
+    }
+    counts
+}
+
+fn read_trimmed<P: AsRef<Path>>(path: P) -> Option<String> {
+    fs::read_to_string(path)
+        .ok()
+        .map(|content| content.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_source_name() {
+        assert_eq!(classify_source_name("rtc0"), Some(WakeReason::RtcAlarm));
+        assert_eq!(
+            classify_source_name("alarmtimer.0.auto"),
+            Some(WakeReason::RtcAlarm)
+        );
+        assert_eq!(
+            classify_source_name("sunxi-nmi 0 Level rtc"),
+            Some(WakeReason::RtcAlarm)
+        );
+        assert_eq!(
+            classify_source_name("gpio-sunxi 6 Edge modem-ri"),
+            Some(WakeReason::Modem)
+        );
+        assert_eq!(classify_source_name("qcom,ipa"), Some(WakeReason::Modem));
+        assert_eq!(
+            classify_source_name("pm8941_pwrkey"),
+            Some(WakeReason::PowerButton)
+        );
+        assert_eq!(
+            classify_source_name("gpio-keys"),
+            Some(WakeReason::PowerButton)
+        );
+        assert_eq!(classify_source_name("usb-typec"), Some(WakeReason::Usb));
+        assert_eq!(
+            classify_source_name("usb-power-supply"),
+            Some(WakeReason::Usb)
+        );
+        assert_eq!(
+            classify_source_name("pm8941-charger power"),
+            Some(WakeReason::Usb)
+        );
+        assert_eq!(classify_source_name("gpio-ri"), None);
+        assert_eq!(classify_source_name("ACPI:Ged"), None);
+    }
+
+    #[test]
+    fn test_irq_description() {
+        let interrupts = "           CPU0       CPU1
+ 24:          1          0  IO-APIC   5-edge      ACPI:Ged
+ 45:         12          3  sunxi-nmi   0 Level     rtc
+";
+        assert_eq!(
+            irq_description(interrupts, "45"),
+            Some("sunxi-nmi 0 Level rtc".to_string())
+        );
+        assert_eq!(
+            irq_description(interrupts, "24"),
+            Some("IO-APIC 5-edge ACPI:Ged".to_string())
+        );
+        assert_eq!(irq_description(interrupts, "4"), None);
+    }
+
+    #[test]
+    fn test_increased_wakeup_sources() {
+        let before = HashMap::from([("rtc0".to_string(), 3), ("usb".to_string(), 1)]);
+        let after = HashMap::from([
+            ("rtc0".to_string(), 3),
+            ("usb".to_string(), 2),
+            ("modem".to_string(), 1),
+        ]);
+        assert_eq!(
+            increased_wakeup_sources(&before, &after),
+            vec!["modem".to_string(), "usb".to_string()]
+        );
+    }
+}
@@ -0,0 +1,64 @@
+use crate::dbus_api;
+use crate::history_manager;
+use log::{debug, error, warn};
+use std::path::PathBuf;
+use zbus::dbus_interface;
+use zbus::fdo;
+use zbus::Connection as ZbusConnection;
+
+pub struct ControlWorker {
+    system_dbus_conn: Option<ZbusConnection>,
+}
+
+impl ControlWorker {
+    pub fn new(system_dbus_conn: Option<ZbusConnection>) -> Self {
+        Self { system_dbus_conn }
+    }
+
+    pub async fn handle_serve_control_interface(&mut self, history_file: Option<PathBuf>) {
+        let system_dbus_conn = match self.system_dbus_conn.as_ref() {
+            Some(conn) => conn,
+            None => {
+                warn!("Not serving control interface, system D-Bus is not connected.");
+                return;
+            }
+        };
+        let interface = ControlInterface { history_file };
+        if let Err(e) = system_dbus_conn
+            .object_server()
+            .at(dbus_api::OBJECT_PATH, interface)
+            .await
+        {
+            error!("Failed to serve control interface: {}", e);
+            return;
+        }
+        // Signals and methods work via the unique name too, the well
+        // known name is a convenience which requires D-Bus policy to
+        // be installed.
+        match system_dbus_conn.request_name(dbus_api::BUS_NAME).await {
+            Ok(_) => debug!("Acquired D-Bus name '{}'.", dbus_api::BUS_NAME),
+            Err(e) => warn!(
+                "Failed to acquire D-Bus name '{}': {}",
+                dbus_api::BUS_NAME,
+                e
+            ),
+        }
+    }
+}
+
+struct ControlInterface {
+    history_file: Option<PathBuf>,
+}
+
+#[dbus_interface(name = "io.github.jistr.Waketimed")]
+impl ControlInterface {
+    /// Suspend/resume history statistics, formatted as YAML.
+    #[dbus_interface(name = "GetHistoryStats")]
+    async fn get_history_stats(&self) -> fdo::Result<String> {
+        let history_file = self
+            .history_file
+            .as_ref()
+            .ok_or_else(|| fdo::Error::Failed("History is disabled.".to_string()))?;
+        history_manager::stats_yaml(history_file).map_err(|e| fdo::Error::Failed(format!("{e:#}")))
+    }
+}
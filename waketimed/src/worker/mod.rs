@@ -1,14 +1,18 @@
+mod control_worker;
 mod sleep_worker;
 mod var_worker;
+use self::control_worker::ControlWorker;
 use self::sleep_worker::SleepWorker;
 use self::var_worker::VarWorker;
 use crate::messages::{EngineMsg, WorkerMsg};
+use crate::user_buses::UserBuses;
 use anyhow::Error as AnyError;
 use log::{trace, warn};
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 use zbus::Connection as ZbusConnection;
 
 pub struct Worker {
+    control_worker: ControlWorker,
     sleep_worker: SleepWorker,
     var_worker: VarWorker,
 }
@@ -20,10 +24,17 @@ impl Worker {
             warn!("Unable to connect to system D-Bus, variables and features relying on it will not work. Reason: {}", e);
         }
         let system_dbus_conn = system_dbus_conn.ok();
-        let sleep_worker = SleepWorker::new(engine_send.clone(), system_dbus_conn.clone());
-        let var_worker = VarWorker::new(engine_send, system_dbus_conn);
+        let user_buses = system_dbus_conn.clone().map(UserBuses::new);
+        let control_worker = ControlWorker::new(system_dbus_conn.clone());
+        let sleep_worker = SleepWorker::new(
+            engine_send.clone(),
+            system_dbus_conn.clone(),
+            user_buses.clone(),
+        );
+        let var_worker = VarWorker::new(engine_send, system_dbus_conn, user_buses);
 
         Self {
+            control_worker,
             sleep_worker,
             var_worker,
         }
@@ -34,7 +45,25 @@ impl Worker {
         trace!("Received WorkerMsg::{:?}.", &msg);
         match msg {
             CallVarPoll(var_name) => self.var_worker.handle_call_var_poll(var_name).await,
+            EmitSleepApproaching(until_suspend, notify_desktop) => {
+                self.sleep_worker
+                    .handle_emit_sleep_approaching(until_suspend, notify_desktop)
+                    .await
+            }
+            EmitSleepApproachingCancelled(notify_desktop) => {
+                self.sleep_worker
+                    .handle_emit_sleep_approaching_cancelled(notify_desktop)
+                    .await
+            }
             LoadPollVarFns(var_def) => self.var_worker.handle_load_poll_var_fns(var_def).await,
+            ServeControlInterface(history_file) => {
+                self.control_worker
+                    .handle_serve_control_interface(history_file)
+                    .await
+            }
+            ScheduleSuspendTick(millis) => {
+                self.sleep_worker.handle_schedule_suspend_tick(millis).await
+            }
             SpawnPollVarInterval(interval) => {
                 self.var_worker
                     .handle_spawn_poll_var_interval(interval)
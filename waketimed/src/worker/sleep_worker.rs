@@ -1,24 +1,39 @@
+use crate::dbus_api;
 use crate::messages::EngineMsg;
+use crate::user_buses::UserBuses;
 use anyhow::{anyhow, Error as AnyError};
 use futures_util::stream::StreamExt;
-use log::{debug, error, info, warn};
+use log::{debug, error, info, trace, warn};
+use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::mpsc::UnboundedSender;
+use tokio::task::JoinHandle;
+use tokio::time::{self, Duration};
 use zbus::Connection as ZbusConnection;
 
+const NOTIFICATION_APP_NAME: &str = "waketimed";
+
 pub struct SleepWorker {
     engine_send: UnboundedSender<EngineMsg>,
     system_dbus_conn: Option<ZbusConnection>,
+    user_buses: Option<UserBuses>,
+    // Desktop notification IDs keyed by UID of the notified user.
+    sleep_approaching_notifications: HashMap<u32, u32>,
+    suspend_tick_task: Option<JoinHandle<()>>,
 }
 
 impl SleepWorker {
     pub fn new(
         engine_send: UnboundedSender<EngineMsg>,
         system_dbus_conn: Option<ZbusConnection>,
+        user_buses: Option<UserBuses>,
     ) -> Self {
         Self {
             engine_send,
             system_dbus_conn,
+            user_buses,
+            sleep_approaching_notifications: HashMap::new(),
+            suspend_tick_task: None,
         }
     }
 
@@ -72,7 +87,7 @@ impl SleepWorker {
         }
 
         info!("Requesting suspend.");
-        if matches!(self.system_dbus_conn, None) {
+        if self.system_dbus_conn.is_none() {
             error!("Attempted to suspend but system_dbus_conn is None.");
             return;
         }
@@ -88,7 +103,168 @@ impl SleepWorker {
             .await;
         match suspend_res {
             Ok(_) => info!("Suspend request successful."),
-            Err(e) => warn!("Suspend request unsuccessful: {}", e),
+            Err(e) => {
+                warn!("Suspend request unsuccessful: {}", e);
+                self.engine_send
+                    .send(EngineMsg::SuspendFailed(e.to_string()))
+                    .unwrap_or_else(|e| error!("Failed to send EngineMsg::SuspendFailed: {}", e));
+            }
+        }
+    }
+
+    pub async fn handle_schedule_suspend_tick(&mut self, millis: u64) {
+        if let Some(task) = self.suspend_tick_task.take() {
+            trace!("Aborting old suspend tick task.");
+            task.abort();
+            task.await.ok();
+        }
+        let engine_send = self.engine_send.clone();
+        self.suspend_tick_task = Some(tokio::spawn(async move {
+            time::sleep(Duration::from_millis(millis)).await;
+            engine_send
+                .send(EngineMsg::SuspendTick)
+                .unwrap_or_else(|e| error!("Failed to send EngineMsg::SuspendTick: {}", e));
+        }));
+    }
+
+    pub async fn handle_emit_sleep_approaching(
+        &mut self,
+        until_suspend: u64,
+        notify_desktop: bool,
+    ) {
+        let system_dbus_conn = match self.system_dbus_conn.as_ref() {
+            Some(conn) => conn.clone(),
+            None => {
+                error!("Attempted to emit SleepApproaching but system_dbus_conn is None.");
+                return;
+            }
+        };
+        let emit_res = system_dbus_conn
+            .emit_signal(
+                None::<()>,
+                dbus_api::OBJECT_PATH,
+                dbus_api::INTERFACE,
+                dbus_api::SIGNAL_SLEEP_APPROACHING,
+                &(until_suspend,),
+            )
+            .await;
+        if let Err(e) = emit_res {
+            warn!("Failed to emit SleepApproaching signal: {}", e);
+        }
+
+        if notify_desktop {
+            let body = format!(
+                "The device will go to sleep in {} seconds.",
+                until_suspend.div_ceil(1000)
+            );
+            self.notify_desktops("Sleep approaching", &body).await;
+        }
+    }
+
+    pub async fn handle_emit_sleep_approaching_cancelled(&mut self, notify_desktop: bool) {
+        let system_dbus_conn = match self.system_dbus_conn.as_ref() {
+            Some(conn) => conn.clone(),
+            None => {
+                error!("Attempted to emit SleepApproachingCancelled but system_dbus_conn is None.");
+                return;
+            }
+        };
+        let emit_res = system_dbus_conn
+            .emit_signal(
+                None::<()>,
+                dbus_api::OBJECT_PATH,
+                dbus_api::INTERFACE,
+                dbus_api::SIGNAL_SLEEP_APPROACHING_CANCELLED,
+                &(),
+            )
+            .await;
+        if let Err(e) = emit_res {
+            warn!("Failed to emit SleepApproachingCancelled signal: {}", e);
+        }
+
+        if notify_desktop {
+            self.close_desktop_notifications().await;
+        }
+    }
+
+    async fn notify_desktops(&mut self, summary: &str, body: &str) {
+        let user_conns = match self.active_user_connections().await {
+            Ok(conns) => conns,
+            Err(e) => {
+                warn!("Cannot send desktop notifications: {:#}", e);
+                return;
+            }
+        };
+        for (uid, conn) in user_conns.iter() {
+            let replaces_id = self
+                .sleep_approaching_notifications
+                .get(uid)
+                .copied()
+                .unwrap_or(0);
+            let notify_res = conn
+                .call_method(
+                    Some("org.freedesktop.Notifications"),
+                    "/org/freedesktop/Notifications",
+                    Some("org.freedesktop.Notifications"),
+                    "Notify",
+                    &(
+                        NOTIFICATION_APP_NAME,
+                        replaces_id,
+                        "",
+                        summary,
+                        body,
+                        Vec::<&str>::new(),
+                        HashMap::<&str, zvariant::Value>::new(),
+                        -1_i32, // expiration timeout chosen by the notification server
+                    ),
+                )
+                .await;
+            match notify_res.and_then(|msg| msg.body::<u32>()) {
+                Ok(id) => {
+                    self.sleep_approaching_notifications.insert(*uid, id);
+                }
+                Err(e) => warn!("Failed to send desktop notification to user {}: {}", uid, e),
+            }
+        }
+    }
+
+    async fn close_desktop_notifications(&mut self) {
+        if self.sleep_approaching_notifications.is_empty() {
+            return;
+        }
+        let user_conns = match self.active_user_connections().await {
+            Ok(conns) => conns,
+            Err(e) => {
+                warn!("Cannot close desktop notifications: {:#}", e);
+                return;
+            }
+        };
+        for (uid, conn) in user_conns.iter() {
+            if let Some(id) = self.sleep_approaching_notifications.get(uid) {
+                let close_res = conn
+                    .call_method(
+                        Some("org.freedesktop.Notifications"),
+                        "/org/freedesktop/Notifications",
+                        Some("org.freedesktop.Notifications"),
+                        "CloseNotification",
+                        &(*id,),
+                    )
+                    .await;
+                if let Err(e) = close_res {
+                    warn!(
+                        "Failed to close desktop notification of user {}: {}",
+                        uid, e
+                    );
+                }
+            }
+        }
+        self.sleep_approaching_notifications.clear();
+    }
+
+    async fn active_user_connections(&self) -> Result<Vec<(u32, ZbusConnection)>, AnyError> {
+        match self.user_buses.as_ref() {
+            Some(user_buses) => user_buses.active_connections().await,
+            None => Err(anyhow!("System D-Bus is not connected.")),
         }
     }
 
@@ -1,5 +1,6 @@
 use crate::core::vars::{VarDef, VarName};
 use crate::messages::EngineMsg;
+use crate::user_buses::UserBuses;
 use crate::var_creation_context::VarCreationContext;
 use crate::var_fns::{new_poll_var_fns, PollVarFns};
 use anyhow::Context;
@@ -22,12 +23,13 @@ impl VarWorker {
     pub fn new(
         engine_send: UnboundedSender<EngineMsg>,
         system_dbus_conn: Option<ZbusConnection>,
+        user_buses: Option<UserBuses>,
     ) -> Self {
         Self {
             engine_send,
             poll_var_fns: HashMap::new(),
             poll_var_task: None,
-            var_creation_context: VarCreationContext::new(system_dbus_conn),
+            var_creation_context: VarCreationContext::new(system_dbus_conn, user_buses),
         }
     }
 
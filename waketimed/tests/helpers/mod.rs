@@ -135,14 +135,10 @@ impl Supervisor {
         .with_context(|| format!("Failed waiting for stderr substrings {substrs:?}"))
     }
 
-    pub fn wait_upto_ms_or_kill<R: 'static, F: 'static>(
-        &mut self,
-        timeout: u64,
-        func: F,
-    ) -> Result<R, AnyError>
+    pub fn wait_upto_ms_or_kill<R, F>(&mut self, timeout: u64, func: F) -> Result<R, AnyError>
     where
-        R: Send,
-        F: FnOnce() -> R + Send,
+        R: Send + 'static,
+        F: FnOnce() -> R + Send + 'static,
     {
         wait_upto_ms(timeout, func).or_else(|e| {
             signal::kill(Pid::from_raw(self.pid as i32), Signal::SIGKILL)
@@ -169,10 +165,10 @@ impl Supervisor {
     }
 }
 
-pub fn wait_upto_ms<R: 'static, F: 'static>(timeout: u64, func: F) -> Result<R, AnyError>
+pub fn wait_upto_ms<R, F>(timeout: u64, func: F) -> Result<R, AnyError>
 where
-    R: Send,
-    F: FnOnce() -> R + Send,
+    R: Send + 'static,
+    F: FnOnce() -> R + Send + 'static,
 {
     #[allow(clippy::mutex_atomic)]
     let finished_setter = Arc::new((Mutex::new(false), Condvar::new()));
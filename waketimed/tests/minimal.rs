@@ -19,6 +19,7 @@ fn test_minimal() -> Result<(), AnyError> {
     let mut cmd = helpers::waketimed_command();
     cmd.env("WAKETIMED_LOG", "waketimed=trace");
     cmd.env("WAKETIMED_CONFIG_DIR", "");
+    cmd.env("WAKETIMED_STATE_DIR", "");
     cmd.env("WAKETIMED_ALLOWED_CHASSIS_TYPES", "all");
     let wtd_proc = cmd.spawn().context("Failed to spawn waketimed process.")?;
     let mut supervisor = helpers::Supervisor::new(wtd_proc);